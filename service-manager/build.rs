@@ -1,5 +1,10 @@
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tonic_build::compile_protos("../proto/service_manager.proto").unwrap();
+    let out_dir = std::path::PathBuf::from(std::env::var("OUT_DIR")?);
+
+    tonic_build::configure()
+        .file_descriptor_set_path(out_dir.join("service_manager_descriptor.bin"))
+        .compile(&["../proto/service_manager.proto"], &["../proto"])
+        .unwrap();
 
     Ok(())
 }
@@ -0,0 +1,319 @@
+use std::{collections::HashMap, io, process::ExitStatus, time::Duration};
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use tokio::fs::read_to_string;
+
+pub const DEFAULT_STOP_TIMEOUT: Duration = Duration::from_millis(5000);
+
+pub const MASKED_VALUE: &str = "********";
+
+pub const DEFAULT_HEALTH_CHECK_INTERVAL: Duration = Duration::from_millis(5000);
+
+pub const DEFAULT_HEALTH_CHECK_TIMEOUT: Duration = Duration::from_millis(1000);
+
+pub const DEFAULT_UNHEALTHY_THRESHOLD: u32 = 3;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartPolicy {
+    #[default]
+    Never,
+    OnFailure,
+    Always,
+}
+
+impl RestartPolicy {
+    pub fn should_restart(&self, status: &io::Result<ExitStatus>) -> bool {
+        match self {
+            RestartPolicy::Never => false,
+            RestartPolicy::OnFailure => !matches!(status, Ok(status) if status.success()),
+            RestartPolicy::Always => true,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum HealthCheckKind {
+    /// Healthy if a TCP connection to `addr` can be established.
+    Tcp { addr: String },
+    /// Healthy if `grpc.health.v1.Health/Check` at `addr` (e.g. `http://[::1]:11000`) answers
+    /// SERVING for `service`.
+    Grpc {
+        addr: String,
+        #[serde(default)]
+        service: String,
+    },
+    /// Healthy if the command exits successfully.
+    Command {
+        program: String,
+        #[serde(default)]
+        args: Vec<String>,
+    },
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct HealthCheckConfig {
+    #[serde(flatten)]
+    pub kind: HealthCheckKind,
+    pub interval_ms: Option<u64>,
+    pub timeout_ms: Option<u64>,
+    /// Number of consecutive failures before the service is considered unhealthy.
+    pub unhealthy_threshold: Option<u32>,
+    pub restart_on_unhealthy: Option<bool>,
+}
+
+impl HealthCheckConfig {
+    pub fn interval(&self) -> Duration {
+        self.interval_ms
+            .map(Duration::from_millis)
+            .unwrap_or(DEFAULT_HEALTH_CHECK_INTERVAL)
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout_ms
+            .map(Duration::from_millis)
+            .unwrap_or(DEFAULT_HEALTH_CHECK_TIMEOUT)
+    }
+
+    pub fn unhealthy_threshold(&self) -> u32 {
+        self.unhealthy_threshold
+            .unwrap_or(DEFAULT_UNHEALTHY_THRESHOLD)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct ServiceConfig {
+    pub program: String,
+    pub default_args: Vec<String>,
+    pub default_start: Option<bool>,
+    pub allow_args_override: Option<bool>,
+    pub stop_timeout_ms: Option<u64>,
+    pub restart: Option<RestartPolicy>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// Variables loaded from this file are treated as secrets.
+    pub env_file: Option<String>,
+    /// Variables of `env_file`, read by `Config::load` so that reading the file can not fail later.
+    #[serde(skip)]
+    pub env_file_vars: HashMap<String, String>,
+    /// Names of variables in `env` whose values are masked in the status.
+    #[serde(default)]
+    pub secret_env: Vec<String>,
+    pub cwd: Option<String>,
+    pub health_check: Option<HealthCheckConfig>,
+}
+
+impl ServiceConfig {
+    pub fn stop_timeout(&self) -> Duration {
+        self.stop_timeout_ms
+            .map(Duration::from_millis)
+            .unwrap_or(DEFAULT_STOP_TIMEOUT)
+    }
+
+    /// Variables passed to the process. `env` takes precedence over `env_file`.
+    pub fn environment(&self) -> HashMap<String, String> {
+        let mut environment = self.env_file_vars.clone();
+
+        environment.extend(self.env.clone());
+
+        environment
+    }
+
+    /// Same as `environment` but with the secret values replaced by `MASKED_VALUE`.
+    pub fn masked_environment(&self) -> HashMap<String, String> {
+        let mut environment: HashMap<String, String> = self
+            .env_file_vars
+            .keys()
+            .map(|key| (key.clone(), MASKED_VALUE.to_string()))
+            .collect();
+
+        environment.extend(self.env.iter().map(|(key, value)| {
+            if self.secret_env.contains(key) {
+                (key.clone(), MASKED_VALUE.to_string())
+            } else {
+                (key.clone(), value.clone())
+            }
+        }));
+
+        environment
+    }
+}
+
+/// Reads `KEY=VALUE` lines. Empty lines and lines starting with `#` are ignored and values may
+/// be quoted.
+async fn read_env_file(path: &str) -> Result<HashMap<String, String>> {
+    let content = read_to_string(path)
+        .await
+        .map_err(|e| anyhow!("Failed to load env file {} {:?}", path, e))?;
+
+    parse_env(&content)
+}
+
+fn parse_env(content: &str) -> Result<HashMap<String, String>> {
+    let mut environment = HashMap::new();
+
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let line = line.strip_prefix("export ").unwrap_or(line);
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Invalid env line {}", number + 1))?;
+
+        let value = value.trim();
+        let value = ['"', '\'']
+            .iter()
+            .find_map(|quote| {
+                value
+                    .strip_prefix(*quote)
+                    .and_then(|value| value.strip_suffix(*quote))
+            })
+            .unwrap_or(value);
+
+        environment.insert(key.trim().to_string(), value.to_string());
+    }
+
+    Ok(environment)
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ServerConfig {
+    pub service_manager_addr: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Config {
+    pub services: HashMap<String, ServiceConfig>,
+    pub server: ServerConfig,
+}
+
+impl Config {
+    pub async fn load(path: &str) -> Result<Config> {
+        let config_string = read_to_string(path)
+            .await
+            .map_err(|e| anyhow!("Failed to load config! {:?}", e))?;
+
+        let mut config = serde_json::from_str::<Config>(&config_string)
+            .map_err(|e| anyhow!("Invalid config data! {:?}", e))?;
+
+        for service in config.services.values_mut() {
+            if let Some(path) = &service.env_file {
+                service.env_file_vars = read_env_file(path).await?;
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{parse_env, Config, HealthCheckKind, ServiceConfig, MASKED_VALUE};
+
+    #[test]
+    fn parses_env_file() {
+        let environment = parse_env(
+            "# comment\n\nVLC_HOST=localhost\nexport VLC_PORT = 11002\nVLC_PASSWORD=\"12 34\"\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            environment,
+            HashMap::from([
+                ("VLC_HOST".to_string(), "localhost".to_string()),
+                ("VLC_PORT".to_string(), "11002".to_string()),
+                ("VLC_PASSWORD".to_string(), "12 34".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn fails_on_invalid_env_line() {
+        parse_env("VLC_HOST").unwrap_err();
+    }
+
+    #[test]
+    fn masks_secret_env() {
+        let config = ServiceConfig {
+            env: HashMap::from([
+                ("VLC_HOST".to_string(), "localhost".to_string()),
+                ("VLC_PASSWORD".to_string(), "1234".to_string()),
+            ]),
+            secret_env: vec!["VLC_PASSWORD".to_string()],
+            ..ServiceConfig::default()
+        };
+
+        let environment = config.masked_environment();
+
+        assert_eq!(environment["VLC_HOST"], "localhost");
+        assert_eq!(environment["VLC_PASSWORD"], MASKED_VALUE);
+        assert_eq!(config.environment()["VLC_PASSWORD"], "1234");
+    }
+
+    #[tokio::test]
+    async fn loads_env_file_with_config() {
+        let dir = std::env::temp_dir().join(format!("service-manager-{}", nanoid::nanoid!()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let env_file = dir.join("vlc.env");
+        std::fs::write(&env_file, "VLC_PASSWORD=1234\n").unwrap();
+        let config_file = dir.join("config.json");
+        std::fs::write(
+            &config_file,
+            serde_json::json!({
+                "services": {
+                    "vlc": {"program": "vlc", "default_args": [], "env_file": env_file}
+                },
+                "server": {"service_manager_addr": "[::1]:11001"}
+            })
+            .to_string(),
+        )
+        .unwrap();
+
+        let config = Config::load(config_file.to_str().unwrap()).await.unwrap();
+
+        // NOTE: The file is not read again afterwards.
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let vlc = &config.services["vlc"];
+        assert_eq!(vlc.environment()["VLC_PASSWORD"], "1234");
+        assert_eq!(vlc.masked_environment()["VLC_PASSWORD"], MASKED_VALUE);
+    }
+
+    #[test]
+    fn parses_health_check() {
+        let config = serde_json::from_str::<ServiceConfig>(
+            r#"{
+                "program": "time-measurement-system",
+                "default_args": [],
+                "health_check": {
+                    "kind": "grpc",
+                    "addr": "http://[::1]:11000",
+                    "interval_ms": 1000,
+                    "restart_on_unhealthy": true
+                }
+            }"#,
+        )
+        .unwrap();
+
+        let health_check = config.health_check.unwrap();
+
+        assert_eq!(
+            health_check.kind,
+            HealthCheckKind::Grpc {
+                addr: "http://[::1]:11000".to_string(),
+                service: "".to_string()
+            }
+        );
+        assert_eq!(health_check.interval().as_millis(), 1000);
+        assert_eq!(health_check.restart_on_unhealthy, Some(true));
+    }
+}
@@ -0,0 +1,192 @@
+use anyhow::{anyhow, bail, Result};
+use tokio::{net::TcpStream, process};
+use tonic::transport::Channel;
+use tonic_health::pb::{
+    health_check_response::ServingStatus, health_client::HealthClient, HealthCheckRequest,
+};
+
+use crate::config::{HealthCheckConfig, HealthCheckKind};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Health {
+    /// Not checked yet since the process started, or the process is not running.
+    #[default]
+    Unknown,
+    Healthy,
+    Unhealthy,
+}
+
+impl Health {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Health::Unknown => "unknown",
+            Health::Healthy => "healthy",
+            Health::Unhealthy => "unhealthy",
+        }
+    }
+}
+
+/// Tracks consecutive failures of a health check.
+#[derive(Debug, Default)]
+pub struct HealthState {
+    pub health: Health,
+    failures: u32,
+}
+
+impl HealthState {
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Records the result of a check and returns the resulting health.
+    pub fn record(&mut self, result: &Result<()>, unhealthy_threshold: u32) -> Health {
+        match result {
+            Ok(_) => {
+                self.failures = 0;
+                self.health = Health::Healthy;
+            }
+            Err(_) => {
+                self.failures += 1;
+                if self.failures >= unhealthy_threshold {
+                    self.health = Health::Unhealthy;
+                }
+            }
+        }
+
+        self.health
+    }
+}
+
+pub async fn check(config: &HealthCheckConfig) -> Result<()> {
+    tokio::time::timeout(config.timeout(), check_kind(&config.kind))
+        .await
+        .map_err(|_| anyhow!("Health check timed out"))?
+}
+
+async fn check_kind(kind: &HealthCheckKind) -> Result<()> {
+    match kind {
+        HealthCheckKind::Tcp { addr } => {
+            TcpStream::connect(addr).await?;
+        }
+        HealthCheckKind::Grpc { addr, service } => {
+            let channel = Channel::from_shared(addr.clone())?.connect().await?;
+
+            let status = HealthClient::new(channel)
+                .check(HealthCheckRequest {
+                    service: service.clone(),
+                })
+                .await?
+                .into_inner()
+                .status();
+
+            if status != ServingStatus::Serving {
+                bail!("Service is {:?}", status);
+            }
+        }
+        HealthCheckKind::Command { program, args } => {
+            let status = process::Command::new(program)
+                .args(args)
+                .kill_on_drop(true)
+                .status()
+                .await?;
+
+            if !status.success() {
+                bail!("Health check command exited with {:?}", status);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+
+    use super::{check, Health, HealthState};
+    use crate::config::{HealthCheckConfig, HealthCheckKind};
+
+    fn config(kind: HealthCheckKind) -> HealthCheckConfig {
+        HealthCheckConfig {
+            kind,
+            interval_ms: None,
+            timeout_ms: Some(1000),
+            unhealthy_threshold: None,
+            restart_on_unhealthy: None,
+        }
+    }
+
+    #[test]
+    fn becomes_unhealthy_after_threshold() {
+        let mut state = HealthState::default();
+
+        assert_eq!(state.record(&Err(anyhow!("")), 2), Health::Unknown);
+        assert_eq!(state.record(&Err(anyhow!("")), 2), Health::Unhealthy);
+        assert_eq!(state.record(&Ok(()), 2), Health::Healthy);
+        assert_eq!(state.record(&Err(anyhow!("")), 2), Health::Healthy);
+    }
+
+    #[tokio::test]
+    async fn tcp_check() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        check(&config(HealthCheckKind::Tcp { addr: addr.clone() }))
+            .await
+            .unwrap();
+
+        drop(listener);
+
+        check(&config(HealthCheckKind::Tcp { addr }))
+            .await
+            .unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn grpc_check() {
+        let (mut reporter, service) = tonic_health::server::health_reporter();
+        reporter
+            .set_service_status("serving", tonic_health::ServingStatus::Serving)
+            .await;
+        reporter
+            .set_service_status("not-serving", tonic_health::ServingStatus::NotServing)
+            .await;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(service)
+                .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener)),
+        );
+
+        let grpc = |service: &str| {
+            config(HealthCheckKind::Grpc {
+                addr: addr.clone(),
+                service: service.to_string(),
+            })
+        };
+
+        check(&grpc("serving")).await.unwrap();
+        check(&grpc("not-serving")).await.unwrap_err();
+        check(&grpc("unknown")).await.unwrap_err();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn command_check() {
+        check(&config(HealthCheckKind::Command {
+            program: "true".to_string(),
+            args: vec![],
+        }))
+        .await
+        .unwrap();
+
+        check(&config(HealthCheckKind::Command {
+            program: "false".to_string(),
+            args: vec![],
+        }))
+        .await
+        .unwrap_err();
+    }
+}
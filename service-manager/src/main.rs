@@ -1,24 +1,37 @@
 use std::{
     collections::HashMap,
+    future::Future,
+    pin::Pin,
     sync::{mpsc::Sender, Arc},
+    time::Duration,
 };
 
 use async_trait::async_trait;
 
-use clap::{command, Parser};
-use serde::Deserialize;
+use clap::Parser;
 use tokio::{
-    fs::read_to_string,
     process, signal,
     sync::{oneshot, Mutex},
+    task::JoinSet,
 };
 
 use anyhow::{anyhow, bail, Result};
 use log::{debug, warn};
 use tonic::{Request, Response, Status};
 
+use crate::{
+    config::{Config, HealthCheckConfig, ServiceConfig, DEFAULT_HEALTH_CHECK_INTERVAL},
+    health_check::{Health, HealthState},
+};
+
+mod config;
+mod health_check;
+
 mod proto {
     tonic::include_proto!("has.servicemanager");
+
+    pub const FILE_DESCRIPTOR_SET: &[u8] =
+        tonic::include_file_descriptor_set!("service_manager_descriptor");
 }
 
 #[derive(Debug, Eq, PartialEq)]
@@ -27,43 +40,48 @@ enum ServiceEventStatus {
     Killed,
 }
 
+#[allow(dead_code)]
 #[derive(Debug)]
 struct ServiceEvent {
     service_id: String,
     status: ServiceEventStatus,
 }
 
+const RESTART_DELAY: Duration = Duration::from_millis(1000);
+
+/// Sent to the process watcher to request termination. The watcher answers through it once
+/// the process has exited.
+type KillRequest = oneshot::Sender<()>;
+
+/// A health check in progress, polled alongside the process so that a stop does not wait for it.
+type PendingCheck = Pin<Box<dyn Future<Output = (HealthCheckConfig, Result<()>)> + Send>>;
+
 #[derive(Debug)]
 struct Service {
     id: String,
-    program: String,
-    default_args: Vec<String>,
-    last_args: Vec<String>,
-    kill: Arc<Mutex<Option<oneshot::Sender<()>>>>,
+    config: Arc<Mutex<ServiceConfig>>,
+    last_args: Arc<Mutex<Vec<String>>>,
+    kill: Arc<Mutex<Option<oneshot::Sender<KillRequest>>>>,
+    health: Arc<Mutex<HealthState>>,
     on_change: Option<Sender<ServiceEvent>>,
-    allow_args_override: bool,
 }
 
 impl Service {
     async fn new(
         service_id: &str,
-        program: &str,
-        default_args: Vec<String>,
-        allow_args_override: bool,
-        default_start: bool,
+        config: &ServiceConfig,
         on_change: Option<Sender<ServiceEvent>>,
     ) -> Result<Self> {
         let mut service = Self {
             id: service_id.to_string(),
-            program: program.to_string(),
-            last_args: default_args.clone(),
-            default_args,
-            allow_args_override,
+            config: Arc::new(Mutex::new(config.clone())),
+            last_args: Arc::new(Mutex::new(config.default_args.clone())),
             kill: Arc::new(Mutex::new(None)),
+            health: Arc::new(Mutex::new(HealthState::default())),
             on_change,
         };
 
-        if default_start {
+        if config.default_start.unwrap_or(false) {
             service.start(None).await?;
         }
 
@@ -73,7 +91,9 @@ impl Service {
     async fn start(&mut self, override_args: Option<Vec<String>>) -> Result<()> {
         debug!("Starting {}", self.id);
 
-        if !self.allow_args_override && override_args.is_some() {
+        let config = self.config.lock().await.clone();
+
+        if !config.allow_args_override.unwrap_or(false) && override_args.is_some() {
             bail!("Override args was disallowed.");
         }
 
@@ -83,19 +103,20 @@ impl Service {
             }
         }
 
-        self.last_args = match &override_args {
+        let args = match &override_args {
             Some(args) => args.clone(),
-            None => self.default_args.clone(),
+            None => config.default_args.clone(),
         };
 
-        let mut process = process::Command::new(&self.program)
-            .args(&self.last_args)
-            .spawn()
-            .map_err(|e| anyhow!("Failed to start by {:?}", e))?;
+        let mut process = spawn(&config, &args)?;
+
+        *self.last_args.lock().await = args;
+
+        self.health.lock().await.reset();
 
         self.on_change(ServiceEventStatus::Start).await;
 
-        let (send, recv) = oneshot::channel::<()>();
+        let (send, mut recv) = oneshot::channel::<KillRequest>();
 
         let on_change = self.on_change.clone();
 
@@ -103,28 +124,142 @@ impl Service {
 
         let kill = self.kill.clone();
 
+        let config = self.config.clone();
+
+        let last_args = self.last_args.clone();
+
+        let health = self.health.clone();
+
+        let mut health_check = health_check_timer(&*config.lock().await);
+
         *self.kill.lock().await = Some(send);
 
         tokio::spawn(async move {
-            tokio::select! {
-                _ = process.wait() => {
-                    debug!("Somehow {} exited.", service_id);
-                    *kill.lock().await = None
-                },
-                _ = recv => {
-                    debug!("Killing {}", service_id);
-                    if let Err(e) = process.kill().await {warn!("Failed to kill due to {:?}", e)}
-                    debug!("Killed {}", service_id);
+            let mut terminated = None;
+
+            let mut checking: Option<PendingCheck> = None;
+
+            // NOTE: Whether Killed was sent for the current process, which exited before a restart.
+            let mut killed_reported = false;
+
+            loop {
+                tokio::select! {
+                    status = process.wait() => {
+                        debug!("Somehow {} exited. ({:?})", service_id, status);
+
+                        checking = None;
+
+                        let config = config.lock().await.clone();
+
+                        if config.restart.unwrap_or_default().should_restart(&status) {
+                            health.lock().await.reset();
+                            send_event(&on_change, &service_id, ServiceEventStatus::Killed);
+                            killed_reported = true;
+
+                            tokio::select! {
+                                _ = tokio::time::sleep(RESTART_DELAY) => {},
+                                request = &mut recv => {
+                                    terminated = request.ok();
+                                    break;
+                                }
+                            }
+
+                            debug!("Restarting {}", service_id);
+
+                            match respawn(&config, &override_args, &last_args).await {
+                                Ok(restarted) => {
+                                    process = restarted;
+                                    health_check = health_check_timer(&config);
+                                    send_event(&on_change, &service_id, ServiceEventStatus::Start);
+                                    killed_reported = false;
+                                    continue;
+                                }
+                                Err(e) => warn!("Failed to restart {} due to {:?}", service_id, e),
+                            }
+                        }
+
+                        *kill.lock().await = None
+                    },
+                    request = &mut recv => {
+                        debug!("Terminating {}", service_id);
+                        if let Err(e) = terminate(&mut process, config.lock().await.stop_timeout()).await {warn!("Failed to kill due to {:?}", e)}
+                        debug!("Killed {}", service_id);
+                        terminated = request.ok();
+                    },
+                    _ = health_check.tick(), if checking.is_none() => {
+                        let Some(health_check_config) = config.lock().await.health_check.clone() else {
+                            continue;
+                        };
+
+                        checking = Some(Box::pin(async move {
+                            let result = health_check::check(&health_check_config).await;
+                            (health_check_config, result)
+                        }));
+
+                        continue;
+                    },
+                    (health_check_config, result) = async { checking.as_mut().unwrap().await }, if checking.is_some() => {
+                        checking = None;
+
+                        if let Err(e) = &result {
+                            debug!("Health check of {} failed due to {:?}", service_id, e);
+                        }
+
+                        let current_health = health
+                            .lock()
+                            .await
+                            .record(&result, health_check_config.unhealthy_threshold());
+
+                        if current_health == Health::Unhealthy && health_check_config.restart_on_unhealthy.unwrap_or(false) {
+                            warn!("{} is unhealthy so restarting", service_id);
+
+                            let config = config.lock().await.clone();
+
+                            if let Err(e) = terminate(&mut process, config.stop_timeout()).await {warn!("Failed to kill due to {:?}", e)}
+
+                            health.lock().await.reset();
+
+                            send_event(&on_change, &service_id, ServiceEventStatus::Killed);
+                            killed_reported = true;
+
+                            tokio::select! {
+                                _ = tokio::time::sleep(RESTART_DELAY) => {},
+                                request = &mut recv => {
+                                    terminated = request.ok();
+                                    break;
+                                }
+                            }
+
+                            match respawn(&config, &override_args, &last_args).await {
+                                Ok(restarted) => {
+                                    process = restarted;
+                                    health_check = health_check_timer(&config);
+                                    send_event(&on_change, &service_id, ServiceEventStatus::Start);
+                                    killed_reported = false;
+                                }
+                                Err(e) => {
+                                    warn!("Failed to restart {} due to {:?}", service_id, e);
+                                    *kill.lock().await = None;
+                                    break;
+                                }
+                            }
+                        }
+
+                        continue;
+                    }
                 }
+
+                break;
             }
 
-            if let Some(on_change) = on_change {
-                if let Err(e) = on_change.send(ServiceEvent {
-                    service_id,
-                    status: ServiceEventStatus::Killed,
-                }) {
-                    warn!("Failed to propagation change {:?}", e);
-                }
+            health.lock().await.reset();
+
+            if !killed_reported {
+                send_event(&on_change, &service_id, ServiceEventStatus::Killed);
+            }
+
+            if let Some(terminated) = terminated {
+                let _ = terminated.send(());
             }
         });
 
@@ -132,9 +267,15 @@ impl Service {
     }
 
     async fn stop(&mut self) -> Result<()> {
-        if let Some(kill) = self.kill.lock().await.take() {
-            kill.send(())
+        let kill = self.kill.lock().await.take();
+
+        if let Some(kill) = kill {
+            let (send, terminated) = oneshot::channel();
+            kill.send(send)
                 .map_err(|e| anyhow!("Failed to kill request {:?}", e))?;
+            terminated
+                .await
+                .map_err(|e| anyhow!("Failed to wait for termination {:?}", e))?;
         } else {
             bail!("Process not found");
         }
@@ -146,53 +287,240 @@ impl Service {
         self.kill.lock().await.is_some()
     }
 
+    /// Replaces the configuration used by the next start (or restart). The running process is
+    /// left untouched. Returns whether the configuration was changed.
+    async fn update(&mut self, config: &ServiceConfig) -> bool {
+        let mut current = self.config.lock().await;
+
+        if *current == *config {
+            return false;
+        }
+
+        debug!("Updating configuration of {}", self.id);
+
+        *current = config.clone();
+
+        true
+    }
+
     async fn on_change(&mut self, status: ServiceEventStatus) {
-        let event = ServiceEvent {
-            service_id: self.id.clone(),
-            status,
-        };
+        send_event(&self.on_change, &self.id, status);
+    }
+}
 
-        if let Some(on_change) = &self.on_change {
-            if let Err(error) = on_change.send(event) {
-                warn!("Failed to promote change ({:?})", error);
-            }
+fn send_event(
+    on_change: &Option<Sender<ServiceEvent>>,
+    service_id: &str,
+    status: ServiceEventStatus,
+) {
+    let event = ServiceEvent {
+        service_id: service_id.to_string(),
+        status,
+    };
+
+    if let Some(on_change) = on_change {
+        if let Err(error) = on_change.send(event) {
+            warn!("Failed to promote change ({:?})", error);
         }
     }
 }
 
+fn spawn(config: &ServiceConfig, args: &[String]) -> Result<process::Child> {
+    let mut command = process::Command::new(&config.program);
+
+    command.args(args).envs(config.environment());
+
+    if let Some(cwd) = &config.cwd {
+        command.current_dir(cwd);
+    }
+
+    command
+        .spawn()
+        .map_err(|e| anyhow!("Failed to start by {:?}", e))
+}
+
+/// Spawns `config` again on restart. Arguments given to `start` are kept, otherwise the current
+/// defaults are used.
+async fn respawn(
+    config: &ServiceConfig,
+    override_args: &Option<Vec<String>>,
+    last_args: &Mutex<Vec<String>>,
+) -> Result<process::Child> {
+    let args = override_args
+        .clone()
+        .unwrap_or_else(|| config.default_args.clone());
+
+    let process = spawn(config, &args)?;
+
+    *last_args.lock().await = args;
+
+    Ok(process)
+}
+
+/// Ticks every health check interval of `config`, starting one interval from now.
+fn health_check_timer(config: &ServiceConfig) -> tokio::time::Interval {
+    let interval = config
+        .health_check
+        .as_ref()
+        .map(|health_check| health_check.interval())
+        .unwrap_or(DEFAULT_HEALTH_CHECK_INTERVAL);
+
+    let mut timer = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+
+    timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    timer
+}
+
+/// Asks the process to exit by SIGTERM and kills it if it is still alive after `timeout`.
+/// On platforms without signals the process is killed immediately.
+async fn terminate(process: &mut process::Child, timeout: Duration) -> Result<()> {
+    #[cfg(unix)]
+    if let Some(pid) = process.id() {
+        use nix::sys::signal::{kill, Signal};
+        use nix::unistd::Pid;
+
+        match kill(Pid::from_raw(pid as i32), Signal::SIGTERM) {
+            Ok(_) => match tokio::time::timeout(timeout, process.wait()).await {
+                Ok(_) => return Ok(()),
+                Err(_) => warn!("Process did not exit within {:?}, killing", timeout),
+            },
+            Err(e) => warn!("Failed to send SIGTERM due to {:?}", e),
+        }
+    }
+
+    #[cfg(not(unix))]
+    let _ = timeout;
+
+    process.kill().await?;
+
+    Ok(())
+}
+
+struct ServiceStatus {
+    id: String,
+    state: String,
+    args: Vec<String>,
+    env: HashMap<String, String>,
+    cwd: String,
+    health: String,
+}
+
+/// Stops `services` concurrently.
+async fn stop_services(services: impl IntoIterator<Item = (String, Service)>) {
+    let mut stopping = JoinSet::new();
+
+    for (id, mut service) in services {
+        stopping.spawn(async move {
+            if service.running().await {
+                if let Err(e) = service.stop().await {
+                    warn!("Failed to terminate service {} due to {:?}", id, e);
+                }
+            }
+        });
+    }
+
+    while stopping.join_next().await.is_some() {}
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct ReloadSummary {
+    added: Vec<String>,
+    removed: Vec<String>,
+    updated: Vec<String>,
+}
+
 struct ServiceManager {
     service: HashMap<String, Service>,
+    config_path: String,
 }
 
 impl ServiceManager {
-    fn new() -> Self {
+    fn new(config_path: &str) -> Self {
         Self {
             service: HashMap::new(),
+            config_path: config_path.to_string(),
         }
     }
 
-    async fn add(
+    /// Re-reads the config file and applies its services.
+    async fn reload(&mut self) -> Result<(ReloadSummary, Vec<(String, Service)>)> {
+        debug!("Reloading {}", self.config_path);
+
+        let config = Config::load(&self.config_path).await?;
+
+        self.apply(&config.services).await
+    }
+
+    /// Adds new services, removes deleted ones and updates the configuration of the changed ones.
+    /// Services whose configuration is unchanged are not touched. If a new service fails to start,
+    /// nothing is applied.
+    ///
+    /// The removed services are returned still running, to be stopped with `stop_services` once
+    /// the manager is unlocked.
+    async fn apply(
         &mut self,
-        id: &str,
-        program: &str,
-        arg: Vec<String>,
-        allow_args_override: bool,
-        default_start: bool,
-    ) -> Result<()> {
-        if self.service.contains_key(id) {
-            bail!("Service {} already exists", id);
+        services: &HashMap<String, ServiceConfig>,
+    ) -> Result<(ReloadSummary, Vec<(String, Service)>)> {
+        let mut summary = ReloadSummary::default();
+
+        let mut added = HashMap::new();
+
+        for (id, config) in services {
+            if self.service.contains_key(id) {
+                continue;
+            }
+
+            match Service::new(id, config, None).await {
+                Ok(service) => {
+                    added.insert(id.clone(), service);
+                }
+                Err(e) => {
+                    stop_services(added).await;
+
+                    bail!("Failed to add service {} due to {:?}", id, e);
+                }
+            }
         }
 
-        debug!("Service {} registered!", id);
+        let removed_ids: Vec<String> = self
+            .service
+            .keys()
+            .filter(|id| !services.contains_key(*id))
+            .cloned()
+            .collect();
 
-        self.service.insert(
-            id.to_string(),
-            Service::new(&id, program, arg, allow_args_override, default_start, None)
-                .await
-                .unwrap(),
-        );
+        let mut removed = Vec::new();
 
-        Ok(())
+        for id in removed_ids {
+            if let Some(service) = self.service.remove(&id) {
+                removed.push((id.clone(), service));
+            }
+
+            debug!("Service {} unregistered!", id);
+
+            summary.removed.push(id);
+        }
+
+        for (id, service) in self.service.iter_mut() {
+            if service.update(&services[id]).await {
+                summary.updated.push(id.clone());
+            }
+        }
+
+        for (id, service) in added {
+            debug!("Service {} registered!", id);
+
+            self.service.insert(id.clone(), service);
+
+            summary.added.push(id);
+        }
+
+        summary.added.sort();
+        summary.removed.sort();
+        summary.updated.sort();
+
+        Ok((summary, removed))
     }
 
     async fn start(&mut self, id: &str, args: Option<Vec<String>>) -> Result<()> {
@@ -211,19 +539,30 @@ impl ServiceManager {
             .await
     }
 
-    async fn status(&mut self) -> Result<Vec<(String, String, Vec<String>)>> {
-        // Fixme Return type
+    async fn stop_all(&mut self) {
+        stop_services(self.service.drain()).await;
+    }
+
+    async fn status(&mut self) -> Result<Vec<ServiceStatus>> {
         let mut services = Vec::new();
         for (id, service) in &mut self.service {
-            services.push((
-                id.to_string(),
-                if service.running().await {
+            let config = service.config.lock().await.clone();
+
+            services.push(ServiceStatus {
+                id: id.to_string(),
+                state: if service.running().await {
                     "running".to_string()
                 } else {
                     "not-running".to_string()
                 },
-                service.last_args.clone(),
-            ));
+                args: service.last_args.lock().await.clone(),
+                env: config.masked_environment(),
+                health: match config.health_check {
+                    Some(_) => service.health.lock().await.health.as_str().to_string(),
+                    None => "".to_string(),
+                },
+                cwd: config.cwd.unwrap_or_default(),
+            });
         }
 
         Ok(services)
@@ -266,7 +605,7 @@ impl proto::service_manager_server::ServiceManager for Arc<Mutex<ServiceManager>
 
     async fn status(
         &self,
-        request: Request<proto::StatusRequest>,
+        _request: Request<proto::StatusRequest>,
     ) -> Result<Response<proto::StatusReply>, Status> {
         let services = self
             .lock()
@@ -275,29 +614,43 @@ impl proto::service_manager_server::ServiceManager for Arc<Mutex<ServiceManager>
             .await
             .map_err(|e| Status::internal(format!("{:?}", e)))?
             .into_iter()
-            .map(|(id, state, args)| proto::Service { id, state, args })
+            .map(|status| proto::Service {
+                id: status.id,
+                state: status.state,
+                args: status.args,
+                env: status.env,
+                cwd: status.cwd,
+                health: status.health,
+            })
             .collect();
         Ok(Response::new(proto::StatusReply { services }))
     }
-}
 
-#[derive(Deserialize, Debug)]
-struct ServiceConfig {
-    program: String,
-    default_args: Vec<String>,
-    default_start: Option<bool>,
-    allow_args_override: Option<bool>,
-}
+    async fn reload(
+        &self,
+        _request: Request<proto::ReloadRequest>,
+    ) -> Result<Response<proto::ReloadReply>, Status> {
+        let (summary, removed_services) = self
+            .lock()
+            .await
+            .reload()
+            .await
+            .map_err(|e| Status::internal(format!("{:?}", e)))?;
 
-#[derive(Deserialize, Debug)]
-struct ServerConfig {
-    service_manager_addr: String,
-}
+        stop_services(removed_services).await;
+
+        let ReloadSummary {
+            added,
+            removed,
+            updated,
+        } = summary;
 
-#[derive(Deserialize, Debug)]
-struct Config {
-    services: HashMap<String, ServiceConfig>,
-    server: ServerConfig,
+        Ok(Response::new(proto::ReloadReply {
+            added,
+            removed,
+            updated,
+        }))
+    }
 }
 
 #[derive(Parser)]
@@ -307,59 +660,121 @@ struct Args {
     config: String,
 }
 
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        let mut terminate = signal::unix::signal(signal::unix::SignalKind::terminate())
+            .unwrap_or_else(|e| panic!("Failed to listen SIGTERM {:?}", e));
+
+        tokio::select! {
+            result = signal::ctrl_c() => result.unwrap_or_else(|e| panic!("Failed to listen SIGINT {:?}", e)),
+            _ = terminate.recv() => {}
+        }
+    }
+
+    #[cfg(not(unix))]
+    signal::ctrl_c()
+        .await
+        .unwrap_or_else(|e| panic!("Failed to listen Ctrl-C {:?}", e));
+
+    debug!("Shutdown requested");
+}
+
+#[cfg(unix)]
+fn reload_on_hangup(service: Arc<Mutex<ServiceManager>>) {
+    let mut hangup = signal::unix::signal(signal::unix::SignalKind::hangup())
+        .unwrap_or_else(|e| panic!("Failed to listen SIGHUP {:?}", e));
+
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            let reloaded = service.lock().await.reload().await;
+
+            match reloaded {
+                Ok((summary, removed)) => {
+                    stop_services(removed).await;
+                    debug!("Reloaded by SIGHUP {:?}", summary)
+                }
+                Err(e) => warn!("Failed to reload due to {:?}", e),
+            }
+        }
+    });
+}
+
 #[tokio::main]
 async fn main() {
     env_logger::init();
 
     let args = Args::parse();
 
-    let config_string = read_to_string(args.config)
+    let (mut health_reporter, health) = tonic_health::server::health_reporter();
+
+    health_reporter
+        .set_service_status("", tonic_health::ServingStatus::NotServing)
+        .await;
+
+    let config = Config::load(&args.config)
         .await
-        .unwrap_or_else(|error| panic!("Failed to load config! {:?}", error));
+        .unwrap_or_else(|error| panic!("{:?}", error));
 
-    let config = serde_json::from_str::<Config>(&config_string)
-        .unwrap_or_else(|error| panic!("Invalid config data! {:?}", error));
+    let service = Arc::new(Mutex::new(ServiceManager::new(&args.config)));
 
-    let mut service = Arc::new(Mutex::new(ServiceManager::new()));
+    // NOTE: Nothing is removed since the manager starts empty.
+    service
+        .lock()
+        .await
+        .apply(&config.services)
+        .await
+        .unwrap_or_else(|e| panic!("Failed to register service due to {:?}", e));
 
-    for (id, service_config) in config.services.iter() {
-        service
-            .lock()
-            .await
-            .add(
-                &id,
-                &service_config.program,
-                service_config.default_args.clone(),
-                service_config.allow_args_override.unwrap_or(false),
-                service_config.default_start.unwrap_or(false),
-            )
-            .await
-            .unwrap_or_else(|e| panic!("Failed to register service due to {:?}", e));
-    }
+    #[cfg(unix)]
+    reload_on_hangup(service.clone());
 
-    tonic::transport::Server::builder()
+    let reflection = tonic_reflection::server::Builder::configure()
+        .register_encoded_file_descriptor_set(proto::FILE_DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(tonic_health::pb::FILE_DESCRIPTOR_SET)
+        .build()
+        .unwrap_or_else(|e| panic!("Failed to build reflection service! {:?}", e));
+
+    health_reporter
+        .set_serving::<proto::service_manager_server::ServiceManagerServer<Arc<Mutex<ServiceManager>>>>()
+        .await;
+
+    health_reporter
+        .set_service_status("", tonic_health::ServingStatus::Serving)
+        .await;
+
+    let server = tonic::transport::Server::builder()
         .accept_http1(true)
+        .add_service(tonic_web::enable(health))
+        .add_service(reflection)
         .add_service(tonic_web::enable(
             proto::service_manager_server::ServiceManagerServer::new(service.clone()),
         ))
-        .serve(config.server.service_manager_addr.parse().unwrap())
-        .await
-        .unwrap();
-
-    signal::ctrl_c().await.unwrap();
-
-    let mut service = service.lock().await;
+        .serve_with_shutdown(
+            config.server.service_manager_addr.parse().unwrap(),
+            async move {
+                shutdown_signal().await;
+
+                health_reporter
+                    .set_service_status("", tonic_health::ServingStatus::NotServing)
+                    .await;
+            },
+        )
+        .await;
 
-    for (id, service) in service.service.iter_mut() {
-        if let Err(e) = service.stop().await {
-            warn!("Failed to terminate service {} due to {:?}", id, e);
-        }
+    if let Err(e) = server {
+        warn!("Server stopped due to {:?}", e);
     }
+
+    service.lock().await.stop_all().await;
 }
 
 #[cfg(test)]
 mod test {
-    use crate::Service;
+    use std::collections::HashMap;
+
+    use crate::config::{HealthCheckConfig, HealthCheckKind, RestartPolicy, ServiceConfig};
+    use crate::{stop_services, ReloadSummary, Service, ServiceEventStatus, ServiceManager};
 
     fn sleep_command() -> &'static str {
         if cfg!(target_os = "windows") {
@@ -369,77 +784,330 @@ mod test {
         }
     }
 
+    fn sleep_config(
+        seconds: &str,
+        allow_args_override: bool,
+        default_start: bool,
+    ) -> ServiceConfig {
+        ServiceConfig {
+            program: sleep_command().to_string(),
+            default_args: vec![seconds.to_string()],
+            default_start: Some(default_start),
+            allow_args_override: Some(allow_args_override),
+            ..ServiceConfig::default()
+        }
+    }
+
     #[tokio::test]
     async fn running() {
+        let mut service = Service::new("", &sleep_config("3", false, true), None)
+            .await
+            .unwrap();
+
+        assert!(service.running().await);
+        tokio::time::sleep(std::time::Duration::from_millis(5000)).await;
+        assert!(!service.running().await);
+    }
+
+    #[tokio::test]
+    async fn non_default_start() {
+        let mut service = Service::new("", &sleep_config("3", true, false), None)
+            .await
+            .unwrap();
+
+        assert!(!service.running().await);
+        service.start(None).await.unwrap();
+        assert!(service.running().await);
+        tokio::time::sleep(std::time::Duration::from_millis(5000)).await;
+        assert!(!service.running().await);
+    }
+
+    #[tokio::test]
+    async fn stop() {
+        let mut service = Service::new("", &sleep_config("3", false, true), None)
+            .await
+            .unwrap();
+
+        assert!(service.running().await);
+        service.stop().await.unwrap();
+        assert!(!service.running().await);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn stop_kills_after_timeout() {
         let mut service = Service::new(
             "",
-            sleep_command(),
-            vec!["3".to_string()],
-            false,
-            true,
+            &ServiceConfig {
+                program: "sh".to_string(),
+                default_args: vec!["-c".to_string(), "trap '' TERM; sleep 10".to_string()],
+                default_start: Some(true),
+                stop_timeout_ms: Some(500),
+                ..ServiceConfig::default()
+            },
             None,
         )
         .await
         .unwrap();
 
-        assert_eq!(service.running().await, true);
-        tokio::time::sleep(std::time::Duration::from_millis(5000)).await;
-        assert_eq!(service.running().await, false);
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+        let started_at = std::time::Instant::now();
+        service.stop().await.unwrap();
+        assert!(started_at.elapsed() < std::time::Duration::from_millis(5000));
+        assert!(!service.running().await);
     }
 
+    #[cfg(unix)]
     #[tokio::test]
-    async fn non_default_start() {
+    async fn start_with_env_and_cwd() {
         let mut service = Service::new(
             "",
-            sleep_command(),
-            vec!["3".to_string()],
-            true,
-            false,
+            &ServiceConfig {
+                program: "sh".to_string(),
+                default_args: vec![
+                    "-c".to_string(),
+                    r#"test "$VLC_HOST" = localhost && test "$(pwd)" = / && sleep 3"#.to_string(),
+                ],
+                default_start: Some(true),
+                env: HashMap::from([("VLC_HOST".to_string(), "localhost".to_string())]),
+                cwd: Some("/".to_string()),
+                ..ServiceConfig::default()
+            },
             None,
         )
         .await
         .unwrap();
 
-        assert_eq!(service.running().await, false);
-        service.start(None).await.unwrap();
-        assert_eq!(service.running().await, true);
+        tokio::time::sleep(std::time::Duration::from_millis(1000)).await;
+        assert!(service.running().await);
+        service.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn start_with_override() {
+        let mut service = Service::new("", &sleep_config("10", true, false), None)
+            .await
+            .unwrap();
+
+        service.start(Some(vec!["3".to_string()])).await.unwrap();
         tokio::time::sleep(std::time::Duration::from_millis(5000)).await;
-        assert_eq!(service.running().await, false);
+        assert!(!service.running().await); // NOTE: assert fails if service survived for 10s
     }
 
     #[tokio::test]
-    async fn stop() {
+    async fn restart_always() {
         let mut service = Service::new(
             "",
-            sleep_command(),
-            vec!["3".to_string()],
-            false,
-            true,
+            &ServiceConfig {
+                restart: Some(RestartPolicy::Always),
+                ..sleep_config("1", false, true)
+            },
             None,
         )
         .await
         .unwrap();
 
-        assert_eq!(service.running().await, true);
+        tokio::time::sleep(std::time::Duration::from_millis(2500)).await;
+        assert!(service.running().await);
         service.stop().await.unwrap();
-        assert_eq!(service.running().await, false);
+        assert!(!service.running().await);
     }
 
     #[tokio::test]
-    async fn start_with_override() {
+    async fn restart_uses_current_config() {
         let mut service = Service::new(
             "",
-            sleep_command(),
-            vec!["10".to_string()],
-            true,
-            false,
+            &ServiceConfig {
+                restart: Some(RestartPolicy::Always),
+                ..sleep_config("1", false, true)
+            },
             None,
         )
         .await
         .unwrap();
 
-        service.start(Some(vec!["3".to_string()])).await.unwrap();
-        tokio::time::sleep(std::time::Duration::from_millis(5000)).await;
-        assert_eq!(service.running().await, false); // NOTE: assert fails if service survived for 10s
+        service
+            .update(&ServiceConfig {
+                restart: Some(RestartPolicy::Always),
+                ..sleep_config("10", false, true)
+            })
+            .await;
+
+        tokio::time::sleep(std::time::Duration::from_millis(2500)).await;
+        assert!(service.running().await);
+        assert_eq!(*service.last_args.lock().await, vec!["10".to_string()]);
+        service.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn restart_keeps_override_args() {
+        let mut service = Service::new(
+            "",
+            &ServiceConfig {
+                restart: Some(RestartPolicy::Always),
+                ..sleep_config("10", true, false)
+            },
+            None,
+        )
+        .await
+        .unwrap();
+
+        service.start(Some(vec!["1".to_string()])).await.unwrap();
+
+        tokio::time::sleep(std::time::Duration::from_millis(2500)).await;
+        assert!(service.running().await);
+        assert_eq!(*service.last_args.lock().await, vec!["1".to_string()]);
+        service.stop().await.unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn restart_on_unhealthy() {
+        let (on_change, events) = std::sync::mpsc::channel();
+
+        let mut service = Service::new(
+            "",
+            &ServiceConfig {
+                health_check: Some(HealthCheckConfig {
+                    kind: HealthCheckKind::Command {
+                        program: "false".to_string(),
+                        args: vec![],
+                    },
+                    interval_ms: Some(200),
+                    timeout_ms: None,
+                    unhealthy_threshold: Some(1),
+                    restart_on_unhealthy: Some(true),
+                }),
+                ..sleep_config("10", false, true)
+            },
+            Some(on_change),
+        )
+        .await
+        .unwrap();
+
+        // NOTE: Unhealthy at 200ms and restarted after RESTART_DELAY, then unhealthy again.
+        tokio::time::sleep(std::time::Duration::from_millis(1700)).await;
+        service.stop().await.unwrap();
+
+        let events = events
+            .try_iter()
+            .map(|event| event.status)
+            .collect::<Vec<_>>();
+
+        // NOTE: Each restart reports the unhealthy process killed before the new one started.
+        assert_eq!(
+            events,
+            vec![
+                ServiceEventStatus::Start,
+                ServiceEventStatus::Killed,
+                ServiceEventStatus::Start,
+                ServiceEventStatus::Killed,
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn stop_does_not_wait_for_health_check() {
+        let mut service = Service::new(
+            "",
+            &ServiceConfig {
+                health_check: Some(HealthCheckConfig {
+                    kind: HealthCheckKind::Command {
+                        program: "sleep".to_string(),
+                        args: vec!["10".to_string()],
+                    },
+                    interval_ms: Some(100),
+                    timeout_ms: Some(20_000),
+                    unhealthy_threshold: None,
+                    restart_on_unhealthy: None,
+                }),
+                ..sleep_config("30", false, true)
+            },
+            None,
+        )
+        .await
+        .unwrap();
+
+        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+
+        let started_at = std::time::Instant::now();
+        service.stop().await.unwrap();
+        assert!(started_at.elapsed() < std::time::Duration::from_millis(5000));
+    }
+
+    #[tokio::test]
+    async fn apply_reports_changes() {
+        let mut manager = ServiceManager::new("");
+
+        let (summary, _) = manager
+            .apply(&HashMap::from([
+                ("kept".to_string(), sleep_config("10", false, false)),
+                ("updated".to_string(), sleep_config("10", false, false)),
+                ("removed".to_string(), sleep_config("10", false, true)),
+            ]))
+            .await
+            .unwrap();
+
+        assert_eq!(summary.added, vec!["kept", "removed", "updated"]);
+
+        let (summary, mut removed) = manager
+            .apply(&HashMap::from([
+                ("kept".to_string(), sleep_config("10", false, false)),
+                ("updated".to_string(), sleep_config("20", true, false)),
+                ("added".to_string(), sleep_config("10", false, false)),
+            ]))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            summary,
+            ReloadSummary {
+                added: vec!["added".to_string()],
+                removed: vec!["removed".to_string()],
+                updated: vec!["updated".to_string()],
+            }
+        );
+        assert!(!manager.service.contains_key("removed"));
+
+        // NOTE: Removed services are left for the caller to stop once the manager is unlocked.
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].0, "removed");
+        assert!(removed[0].1.running().await);
+        stop_services(removed).await;
+        assert_eq!(
+            manager.service["updated"].config.lock().await.default_args,
+            vec!["20".to_string()]
+        );
+    }
+
+    #[tokio::test]
+    async fn apply_nothing_if_add_fails() {
+        let mut manager = ServiceManager::new("");
+
+        manager
+            .apply(&HashMap::from([(
+                "kept".to_string(),
+                sleep_config("10", false, false),
+            )]))
+            .await
+            .unwrap();
+
+        manager
+            .apply(&HashMap::from([
+                ("added".to_string(), sleep_config("10", false, true)),
+                (
+                    "broken".to_string(),
+                    ServiceConfig {
+                        program: "no-such-program".to_string(),
+                        ..sleep_config("10", false, true)
+                    },
+                ),
+            ]))
+            .await
+            .unwrap_err();
+
+        assert_eq!(manager.service.keys().collect::<Vec<_>>(), vec!["kept"]);
     }
 }
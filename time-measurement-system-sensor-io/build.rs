@@ -1,5 +1,10 @@
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tonic_build::compile_protos("../proto/running_observer.proto").unwrap();
+    tonic_build::configure()
+        .compile(
+            &["../proto/running_observer.proto", "../proto/sensor_io.proto"],
+            &["../proto"],
+        )
+        .unwrap();
 
     Ok(())
 }
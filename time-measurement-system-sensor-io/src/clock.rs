@@ -0,0 +1,234 @@
+use log::{debug, warn};
+use serde::Deserialize;
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct ClockConfig {
+    /// Device ticks per millisecond, e.g. `1000` for a microsecond counter.
+    pub ticks_per_ms: f64,
+    /// How often the bridge asks the device for its tick counter.
+    #[serde(default = "default_sync_interval_ms")]
+    pub sync_interval_ms: u64,
+    /// Width of the tick counter in bits if it wraps around, e.g. `32`.
+    pub tick_bits: Option<u32>,
+    /// How long the most precise sample is kept before a newer one replaces it, so that the
+    /// mapping follows the drift of the device.
+    #[serde(default = "default_reanchor_interval_ms")]
+    pub reanchor_interval_ms: u64,
+}
+
+fn default_sync_interval_ms() -> u64 {
+    10_000
+}
+
+fn default_reanchor_interval_ms() -> u64 {
+    300_000
+}
+
+/// A sync reply further than this (plus its round trip) from the mapping means the device was
+/// reset, far more than the drift between re-anchors.
+const RESET_TOLERANCE_US: f64 = 100_000.0;
+
+#[derive(Clone, Copy, Debug)]
+struct Anchor {
+    unixtime_us: f64,
+    /// Unwrapped, see `DeviceClock::unwrap`.
+    ticks: i128,
+    round_trip_us: i64,
+}
+
+/// Maps device ticks to unix time in microseconds. The device answers each sync request with its tick counter,
+/// which is assumed to be read halfway through the round trip.
+pub struct DeviceClock {
+    ticks_per_ms: f64,
+    tick_modulus: Option<i128>,
+    reanchor_interval_us: f64,
+    requested_at: Option<i64>,
+    anchor: Option<Anchor>,
+    /// Unwrapped ticks of the last sync reply.
+    last_ticks: Option<i128>,
+}
+
+impl DeviceClock {
+    pub fn new(config: &ClockConfig) -> Self {
+        if let Some(bits) = config.tick_bits {
+            assert!(
+                (1..=64).contains(&bits),
+                "tick_bits {} is out of 1..=64",
+                bits
+            );
+        }
+
+        DeviceClock {
+            ticks_per_ms: config.ticks_per_ms,
+            tick_modulus: config.tick_bits.map(|bits| 1 << bits),
+            reanchor_interval_us: config.reanchor_interval_ms as f64 * 1000.0,
+            requested_at: None,
+            anchor: None,
+            last_ticks: None,
+        }
+    }
+
+    /// Ticks of a wrapping counter as if it did not wrap, taking the closest to the last sync
+    /// reply. Valid as long as less than half the counter range passes between syncs.
+    fn unwrap(&self, ticks: u64) -> i128 {
+        let ticks = ticks as i128;
+        let (Some(modulus), Some(last)) = (self.tick_modulus, self.last_ticks) else {
+            return ticks;
+        };
+
+        let forward = (ticks - last).rem_euclid(modulus);
+        if forward < modulus / 2 {
+            last + forward
+        } else {
+            last + forward - modulus
+        }
+    }
+
+    fn map(&self, anchor: &Anchor, ticks: i128) -> f64 {
+        let elapsed_us = (ticks - anchor.ticks) as f64 / self.ticks_per_ms * 1000.0;
+
+        anchor.unixtime_us + elapsed_us
+    }
+
+    pub fn sync_requested(&mut self, unixtime_us: i64) {
+        self.requested_at = Some(unixtime_us);
+    }
+
+    /// Takes the tick counter replied to the last sync request.
+    pub fn synced(&mut self, ticks: u64, unixtime_us: i64) {
+        let Some(requested_at) = self.requested_at.take() else {
+            warn!("Ignoring clock {} replied without a request", ticks);
+            return;
+        };
+
+        let round_trip_us = unixtime_us - requested_at;
+        let read_at = requested_at as f64 + round_trip_us as f64 / 2.0;
+
+        if let Some(anchor) = &self.anchor {
+            let off_by_us = self.map(anchor, self.unwrap(ticks)) - read_at;
+
+            if off_by_us.abs() > RESET_TOLERANCE_US + round_trip_us as f64 {
+                warn!(
+                    "Device clock {} is off by {}us, the device may have been reset",
+                    ticks, off_by_us
+                );
+                self.anchor = None;
+                self.last_ticks = None;
+            }
+        }
+
+        let ticks = self.unwrap(ticks);
+        self.last_ticks = Some(ticks);
+
+        // NOTE: Keeping the most precise sample, so that a start and a stop are mapped alike,
+        // until it gets old enough to have drifted.
+        if self.anchor.is_some_and(|anchor| {
+            anchor.round_trip_us <= round_trip_us
+                && read_at - anchor.unixtime_us < self.reanchor_interval_us
+        }) {
+            return;
+        }
+
+        debug!(
+            "Device clock {} synced, round trip {}us",
+            ticks, round_trip_us
+        );
+        self.anchor = Some(Anchor {
+            unixtime_us: read_at,
+            ticks,
+            round_trip_us,
+        });
+    }
+
+    pub fn to_unixtime_us(&self, ticks: u64) -> Option<i64> {
+        let anchor = self.anchor.as_ref()?;
+
+        Some(self.map(anchor, self.unwrap(ticks)).round() as i64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ClockConfig, DeviceClock};
+
+    fn config() -> ClockConfig {
+        ClockConfig {
+            ticks_per_ms: 1000.0,
+            sync_interval_ms: 10_000,
+            tick_bits: None,
+            reanchor_interval_ms: 300_000,
+        }
+    }
+
+    #[test]
+    fn maps_ticks_with_the_best_sample() {
+        let mut clock = DeviceClock::new(&config());
+
+        assert_eq!(clock.to_unixtime_us(0), None);
+
+        clock.sync_requested(1_000_000);
+        clock.synced(5_000_000, 1_010_000);
+        assert_eq!(clock.to_unixtime_us(5_000_000), Some(1_005_000));
+        assert_eq!(clock.to_unixtime_us(5_030_000), Some(1_035_000));
+
+        // NOTE: Slower round trip is less precise.
+        clock.sync_requested(2_000_000);
+        clock.synced(6_000_000, 2_020_000);
+        assert_eq!(clock.to_unixtime_us(5_000_000), Some(1_005_000));
+
+        clock.sync_requested(3_000_000);
+        clock.synced(7_000_000, 3_002_000);
+        assert_eq!(clock.to_unixtime_us(7_000_000), Some(3_001_000));
+
+        clock.synced(8_000_000, 4_000_000);
+        assert_eq!(clock.to_unixtime_us(7_000_000), Some(3_001_000));
+    }
+
+    #[test]
+    fn reanchors_after_interval() {
+        let mut clock = DeviceClock::new(&ClockConfig {
+            reanchor_interval_ms: 60_000,
+            ..config()
+        });
+
+        clock.sync_requested(0);
+        clock.synced(0, 2_000);
+        // NOTE: The device runs 0.1% slow, so the first sample maps later ticks early.
+        clock.sync_requested(60_000_000);
+        clock.synced(59_960_000, 60_010_000);
+
+        assert_eq!(clock.to_unixtime_us(59_960_000), Some(60_005_000));
+    }
+
+    #[test]
+    fn unwraps_ticks() {
+        let mut clock = DeviceClock::new(&ClockConfig {
+            tick_bits: Some(32),
+            ..config()
+        });
+        let wrap = 1u64 << 32;
+
+        clock.sync_requested(0);
+        clock.synced(wrap - 1_000_000, 2_000);
+        assert_eq!(clock.to_unixtime_us(wrap - 1_000_000), Some(1_000));
+        assert_eq!(clock.to_unixtime_us(500_000), Some(1_501_000));
+
+        clock.sync_requested(3_000_000);
+        clock.synced(2_000_000, 3_010_000);
+        assert_eq!(clock.to_unixtime_us(2_000_000), Some(3_001_000));
+        assert_eq!(clock.to_unixtime_us(wrap - 500_000), Some(501_000));
+    }
+
+    #[test]
+    fn reanchors_after_device_reset() {
+        let mut clock = DeviceClock::new(&config());
+
+        clock.sync_requested(0);
+        clock.synced(50_000_000, 2_000);
+        clock.sync_requested(10_000_000);
+        clock.synced(1_000, 10_020_000);
+
+        assert_eq!(clock.to_unixtime_us(1_000), Some(10_010_000));
+        assert_eq!(clock.to_unixtime_us(2_001_000), Some(12_010_000));
+    }
+}
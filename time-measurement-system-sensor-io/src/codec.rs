@@ -0,0 +1,32 @@
+use log::warn;
+use prost::bytes::BytesMut;
+use std::{io, str};
+use tokio_util::codec::Decoder;
+
+pub struct LineCodec;
+
+impl Decoder for LineCodec {
+    type Item = String;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        while let Some(n) = src.as_ref().iter().position(|b| *b == b'\n') {
+            let line = src.split_to(n + 1);
+            match str::from_utf8(line.as_ref()) {
+                Ok(s) => return Ok(Some(s.to_string())),
+                // NOTE: Noise on the line should not stop reading the following triggers.
+                Err(_) => warn!("Skipping invalid string {:?}", line.as_ref()),
+            }
+        }
+        Ok(None)
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let line = self.decode(src)?;
+        if line.is_none() && !src.is_empty() {
+            warn!("Dropping incomplete line {:?}", src.as_ref());
+            src.clear();
+        }
+        Ok(line)
+    }
+}
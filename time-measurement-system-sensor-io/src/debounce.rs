@@ -0,0 +1,58 @@
+use std::time::Duration;
+
+/// Drops triggers following an accepted one too closely, e.g. a photocell chattering while a car
+/// passes. Timestamps are microseconds since the unix epoch.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Debouncer {
+    window_us: i64,
+    last: Option<i64>,
+}
+
+impl Debouncer {
+    pub fn new(window: Duration) -> Self {
+        Debouncer {
+            window_us: window.as_micros() as i64,
+            last: None,
+        }
+    }
+
+    /// Returns `false` if the trigger at `timestamp` should be dropped.
+    pub fn accept(&mut self, timestamp: i64) -> bool {
+        // NOTE: A trigger before the last one means the device clock was resynced, not chattering.
+        if let Some(last) = self.last {
+            if timestamp >= last && timestamp - last < self.window_us {
+                return false;
+            }
+        }
+
+        self.last = Some(timestamp);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::Debouncer;
+
+    #[test]
+    fn drops_triggers_within_window_of_accepted_one() {
+        let mut debouncer = Debouncer::new(Duration::from_millis(100));
+
+        assert!(debouncer.accept(1_000_000));
+        assert!(!debouncer.accept(1_050_000));
+        // NOTE: The window is not extended by dropped triggers.
+        assert!(!debouncer.accept(1_099_999));
+        assert!(debouncer.accept(1_100_000));
+        assert!(debouncer.accept(900_000));
+    }
+
+    #[test]
+    fn accepts_everything_without_window() {
+        let mut debouncer = Debouncer::default();
+
+        assert!(debouncer.accept(1_000_000));
+        assert!(debouncer.accept(1_000_000));
+    }
+}
@@ -0,0 +1,271 @@
+use anyhow::{anyhow, bail};
+use futures::stream;
+use log::{debug, warn};
+use prost::bytes::BytesMut;
+use serde::Deserialize;
+use std::{collections::HashMap, fmt, io, str::FromStr};
+use tokio::{
+    io::AsyncWrite,
+    net::{TcpStream, UdpSocket},
+};
+use tokio_serial::SerialPortBuilderExt;
+use tokio_util::codec::Decoder;
+
+use crate::{
+    clock::ClockConfig,
+    codec::LineCodec,
+    lines,
+    protocol::ProtocolConfig,
+    trigger::{
+        forward_synced_triggers, forward_triggers, get_unixtime_us, Handler, Role, Signaler,
+    },
+    ByteSource, Lines,
+};
+
+/// Large enough for any datagram over ethernet.
+const MAX_DATAGRAM_SIZE: usize = 1500;
+
+/// Where the sensor is connected.
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub enum Input {
+    /// `serial://COM3`
+    Serial { path: String, baud: u32 },
+    /// `tcp://host:port`, connected to the gate.
+    Tcp(String),
+    /// `udp://host:port`, bound locally to receive datagrams from the gate.
+    Udp(String),
+}
+
+impl FromStr for Input {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let (scheme, addr) = input
+            .split_once("://")
+            .ok_or(anyhow!("Input {:?} has no scheme", input))?;
+
+        if addr.is_empty() {
+            bail!("Input {:?} has no address", input);
+        }
+
+        match scheme {
+            "serial" => Ok(Input::Serial {
+                path: addr.to_string(),
+                baud: 9600,
+            }),
+            "tcp" => Ok(Input::Tcp(addr.to_string())),
+            "udp" => Ok(Input::Udp(addr.to_string())),
+            _ => bail!("Unknown input scheme {:?}", scheme),
+        }
+    }
+}
+
+impl TryFrom<String> for Input {
+    type Error = anyhow::Error;
+
+    fn try_from(input: String) -> Result<Self, Self::Error> {
+        input.parse()
+    }
+}
+
+impl fmt::Display for Input {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Input::Serial { path, .. } => write!(f, "serial://{}", path),
+            Input::Tcp(addr) => write!(f, "tcp://{}", addr),
+            Input::Udp(addr) => write!(f, "udp://{}", addr),
+        }
+    }
+}
+
+/// An input listed in the config, forwarded concurrently with the others.
+#[derive(Deserialize, Clone, Debug)]
+pub struct InputConfig {
+    /// Shown in the status, `input` if not set.
+    pub id: Option<String>,
+    pub input: Input,
+    /// Overrides the default baud rate of a serial input.
+    pub baud: Option<u32>,
+    #[serde(default)]
+    pub role: Role,
+    /// Roles by the `channel` group of `protocol`, for a device reporting several gates over one
+    /// input. Triggers of other channels are ignored. `role` applies if empty.
+    #[serde(default)]
+    pub channels: HashMap<String, Role>,
+    /// Triggers within this after a signaled one are dropped.
+    #[serde(default)]
+    pub debounce_ms: u64,
+    #[serde(default)]
+    pub protocol: ProtocolConfig,
+    /// Set when the device stamps triggers with its own tick counter.
+    pub clock: Option<ClockConfig>,
+}
+
+impl InputConfig {
+    pub fn id(&self) -> String {
+        self.id.clone().unwrap_or_else(|| self.input.to_string())
+    }
+
+    pub fn input(&self) -> Input {
+        match (&self.input, self.baud) {
+            (Input::Serial { path, .. }, Some(baud)) => Input::Serial {
+                path: path.clone(),
+                baud,
+            },
+            (input, _) => input.clone(),
+        }
+    }
+}
+
+/// Each datagram ends a line even without a trailing newline.
+pub fn udp_lines(socket: UdpSocket) -> Lines {
+    Box::pin(stream::unfold(
+        (socket, BytesMut::new()),
+        |(socket, mut buffer)| async move {
+            loop {
+                match LineCodec.decode(&mut buffer) {
+                    Ok(Some(line)) => return Some((Ok(line), (socket, buffer))),
+                    Ok(None) => buffer.clear(),
+                    Err(error) => return Some((Err(error), (socket, buffer))),
+                }
+
+                let mut datagram = [0; MAX_DATAGRAM_SIZE];
+                match socket.recv(&mut datagram).await {
+                    Ok(size) => {
+                        buffer.extend_from_slice(&datagram[..size]);
+                        if !buffer.ends_with(b"\n") {
+                            buffer.extend_from_slice(b"\n");
+                        }
+                    }
+                    Err(error) => return Some((Err(error), (socket, buffer))),
+                }
+            }
+        },
+    ))
+}
+
+async fn forward_source<S: ByteSource + AsyncWrite>(
+    source: S,
+    handler: &mut Handler,
+    clock: Option<&ClockConfig>,
+    signaler: &Signaler,
+) -> io::Result<()> {
+    // NOTE: The device may have been reset, so the clock is synced from scratch.
+    match clock {
+        Some(clock) => forward_synced_triggers(source, handler, clock, signaler).await,
+        None => forward_triggers(lines(source), handler, signaler).await,
+    }
+}
+
+/// Opens `input` and forwards triggers until it is closed.
+pub async fn forward_input(
+    input: &Input,
+    handler: &mut Handler,
+    clock: Option<&ClockConfig>,
+    signaler: &Signaler,
+) -> io::Result<()> {
+    let status = handler.status().clone();
+
+    let result = open_and_forward(input, handler, clock, signaler).await;
+
+    status.set_open(false);
+    if let Err(error) = &result {
+        status.failed(get_unixtime_us(), error.to_string());
+    }
+    result
+}
+
+async fn open_and_forward(
+    input: &Input,
+    handler: &mut Handler,
+    clock: Option<&ClockConfig>,
+    signaler: &Signaler,
+) -> io::Result<()> {
+    let status = handler.status().clone();
+
+    match input {
+        Input::Serial { path, baud } => {
+            let serial = tokio_serial::new(path, *baud).open_native_async()?;
+            debug!("Opened {}", input);
+            status.set_open(true);
+
+            forward_source(serial, handler, clock, signaler).await
+        }
+        Input::Tcp(addr) => {
+            let stream = TcpStream::connect(addr).await?;
+            debug!("Connected to {}", input);
+            status.set_open(true);
+
+            forward_source(stream, handler, clock, signaler).await
+        }
+        Input::Udp(addr) => {
+            let socket = UdpSocket::bind(addr).await?;
+            debug!("Listening on {}", input);
+            status.set_open(true);
+
+            if clock.is_some() {
+                warn!("Device clock can not be synced over UDP, stamping triggers on read");
+            }
+
+            forward_triggers(udp_lines(socket), handler, signaler).await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Input, InputConfig};
+    use crate::trigger::Role;
+
+    #[test]
+    fn parses_inputs() {
+        assert_eq!(
+            "tcp://192.168.0.10:5000".parse::<Input>().unwrap(),
+            Input::Tcp("192.168.0.10:5000".to_string())
+        );
+        assert_eq!(
+            "udp://0.0.0.0:5000".parse::<Input>().unwrap(),
+            Input::Udp("0.0.0.0:5000".to_string())
+        );
+        assert_eq!(
+            "serial://COM3".parse::<Input>().unwrap(),
+            Input::Serial {
+                path: "COM3".to_string(),
+                baud: 9600
+            }
+        );
+
+        "COM3".parse::<Input>().unwrap_err();
+        "http://localhost".parse::<Input>().unwrap_err();
+        "tcp://".parse::<Input>().unwrap_err();
+    }
+
+    #[test]
+    fn deserializes_input_configs() {
+        let configs = serde_json::from_str::<Vec<InputConfig>>(
+            r#"[
+                {"input": "serial://COM3", "baud": 115200, "role": "start", "debounce_ms": 500},
+                {"id": "finish", "input": "udp://0.0.0.0:5000", "role": "stop"},
+                {"input": "tcp://gate:5000", "channels": {"c1": "start", "c2": "stop"}}
+            ]"#,
+        )
+        .unwrap();
+
+        assert_eq!(configs[0].id(), "serial://COM3");
+        assert_eq!(
+            configs[0].input(),
+            Input::Serial {
+                path: "COM3".to_string(),
+                baud: 115200
+            }
+        );
+        assert_eq!(configs[0].role, Role::Start);
+        assert_eq!(configs[1].id(), "finish");
+        assert_eq!(configs[1].debounce_ms, 0);
+        assert!(configs[1].channels.is_empty());
+        assert_eq!(configs[2].channels["c2"], Role::Stop);
+
+        serde_json::from_str::<InputConfig>(r#"{"input": "COM3"}"#).unwrap_err();
+    }
+}
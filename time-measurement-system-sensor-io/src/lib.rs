@@ -0,0 +1,44 @@
+use std::{io, pin::Pin};
+use tokio::io::AsyncRead;
+use tokio_stream::Stream;
+use tokio_util::codec::FramedRead;
+
+use crate::codec::LineCodec;
+
+pub mod backoff;
+pub mod clock;
+pub mod codec;
+pub mod debounce;
+pub mod input;
+pub mod protocol;
+pub mod simulate;
+pub mod status;
+pub mod trigger;
+
+pub mod proto {
+    pub mod running_observer {
+        tonic::include_proto!("has.runningobserver");
+    }
+    pub mod sensor_io {
+        tonic::include_proto!("has.sensorio");
+    }
+}
+
+pub type Lines = Pin<Box<dyn Stream<Item = Result<String, io::Error>> + Send>>;
+
+/// Where the sensor lines are read from, e.g. the serial port.
+pub trait ByteSource: AsyncRead + Unpin + Send + 'static {}
+
+impl<T: AsyncRead + Unpin + Send + 'static> ByteSource for T {}
+
+pub fn lines(source: impl ByteSource) -> Lines {
+    Box::pin(FramedRead::new(source, LineCodec))
+}
+
+/// `timestamp` is microseconds since the unix epoch.
+pub(crate) fn timestamp_to_proto(timestamp: i64) -> prost_types::Timestamp {
+    prost_types::Timestamp {
+        seconds: timestamp.div_euclid(1_000_000),
+        nanos: (timestamp.rem_euclid(1_000_000) * 1_000) as i32,
+    }
+}
@@ -1,75 +1,53 @@
 use clap::Parser;
-use futures::stream::StreamExt;
-use log::{debug, error, trace};
-use prost::bytes::BytesMut;
+use futures::future::join_all;
+use log::{error, trace, warn};
 use serde::Deserialize;
-use std::{io, str};
+use std::time::Duration;
 use tokio::fs::read_to_string;
-use tokio_serial::SerialPortBuilderExt;
-use tokio_util::codec::{Decoder, Encoder};
-use tonic::transport::Channel;
-
-use crate::proto::FlipRunningStateCommandRequest;
-
-mod proto {
-    tonic::include_proto!("has.runningobserver");
-}
-
-fn get_unixtime_ms() -> i64 {
-    std::time::SystemTime::now()
-        .duration_since(std::time::SystemTime::UNIX_EPOCH)
-        .unwrap()
-        .as_millis() as i64 // FIXME: High precision timer
-}
-
-async fn on_signal(
-    mut client: proto::running_observer_client::RunningObserverClient<Channel>,
-    timestamp: i64,
-) {
-    debug!("Signaling! timestamp: {}", timestamp);
-    if let Err(e) = client
-        .flip_running_state(FlipRunningStateCommandRequest { timestamp })
-        .await
-    {
-        error!("Failed to flip running state {:?}", e);
-    }
-}
-
-struct LineCodec;
-
-impl Decoder for LineCodec {
-    type Item = String;
-    type Error = io::Error;
-
-    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        let newline = src.as_ref().iter().position(|b| *b == b'\n');
-        if let Some(n) = newline {
-            let line = src.split_to(n + 1);
-            return match str::from_utf8(line.as_ref()) {
-                Ok(s) => Ok(Some(s.to_string())),
-                Err(_) => Err(io::Error::new(io::ErrorKind::Other, "Invalid String")),
-            };
-        }
-        Ok(None)
-    }
-}
-
-impl Encoder<String> for LineCodec {
-    type Error = io::Error;
-
-    fn encode(&mut self, _item: String, _dst: &mut BytesMut) -> Result<(), Self::Error> {
-        Ok(())
-    }
-}
+use tonic::transport::{Endpoint, Server as TonicServer};
+
+use time_measurement_system_sensor_io::{
+    backoff::Backoff,
+    clock::ClockConfig,
+    input::{forward_input, Input, InputConfig},
+    proto::{
+        running_observer::running_observer_client::RunningObserverClient,
+        sensor_io::sensor_io_server::SensorIoServer,
+    },
+    protocol::{Parser as LineParser, ProtocolConfig},
+    simulate,
+    status::StatusBoard,
+    trigger::{forward_triggers, Handler, Role, Signaler},
+    Lines,
+};
+
+/// Inputs open for longer than this are reopened without waiting.
+const MIN_HEALTHY_SESSION: Duration = Duration::from_secs(10);
 
 #[derive(Deserialize, Default)]
 pub struct Server {
     pub addr: String,
+    /// Where the status of the inputs is served, if set.
+    pub sensor_io_addr: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+pub struct Sensor {
+    /// For `--input`. Set when the device stamps triggers with its own tick counter. Simulation
+    /// has no device to sync with, so simulated ticks are stamped on read.
+    pub clock: Option<ClockConfig>,
+    /// For `--input` and `--simulate`.
+    #[serde(default)]
+    pub protocol: ProtocolConfig,
+    #[serde(default)]
+    pub inputs: Vec<InputConfig>,
 }
 
 #[derive(Deserialize, Default)]
 pub struct Config {
     pub server: Server,
+    #[serde(default)]
+    pub sensor: Sensor,
 }
 
 #[derive(Parser)]
@@ -77,10 +55,69 @@ pub struct Config {
 struct Args {
     #[arg(long)]
     config: String,
-    #[arg(long)]
-    com: String,
+    /// Same as `--input serial://<COM>`.
+    #[arg(long, conflicts_with = "input")]
+    com: Option<String>,
     #[arg(long, default_value_t = 9600)]
     baud: u32,
+    /// `serial://<COM>`, `tcp://<host>:<port>` to connect to the gate, or `udp://<host>:<port>`
+    /// to receive datagrams on. Forwarded as a flipping input along with the inputs in the config.
+    #[arg(long)]
+    input: Option<Input>,
+    /// Reads triggers from stdin instead of the input.
+    #[arg(long)]
+    simulate: bool,
+    /// With `--simulate`, replays triggers from a script file instead of stdin.
+    #[arg(long, requires = "simulate")]
+    script: Option<String>,
+}
+
+/// Forwards triggers from `input`, reopening it whenever it fails.
+async fn forward_forever(
+    input: &Input,
+    handler: &mut Handler,
+    clock: Option<&ClockConfig>,
+    signaler: &Signaler,
+) {
+    let mut backoff = Backoff::default();
+
+    loop {
+        let started_at = tokio::time::Instant::now();
+
+        match forward_input(input, handler, clock, signaler).await {
+            Ok(_) => warn!("{} was closed", input),
+            Err(error) => error!("Failed to read line from {} {:?}", input, error),
+        }
+
+        // NOTE: Backing off from scratch after a long session, but not spinning on a dead device.
+        if started_at.elapsed() > MIN_HEALTHY_SESSION {
+            backoff.reset();
+        }
+
+        tokio::time::sleep(backoff.next_delay()).await;
+    }
+}
+
+fn serve_status(server: &Server, board: &StatusBoard) {
+    let Some(addr) = &server.sensor_io_addr else {
+        return;
+    };
+    let addr = addr
+        .parse()
+        .unwrap_or_else(|error| panic!("Invalid sensor_io_addr! {:?}", error));
+
+    // NOTE: gRPC-Web lets the GUI ask directly.
+    let service = tonic_web::enable(SensorIoServer::new(board.clone()));
+    tokio::spawn(async move {
+        if let Err(error) = TonicServer::builder()
+            .accept_http1(true)
+            .add_service(service)
+            .serve(addr)
+            .await
+        {
+            error!("Failed to serve the status {:?}", error);
+        }
+    });
 }
 
 #[tokio::main]
@@ -96,27 +133,102 @@ async fn main() {
     let config = serde_json::from_str::<Config>(&config_string)
         .unwrap_or_else(|error| panic!("Invalid config data! {:?}", error));
 
-    let serial = tokio_serial::new(args.com, args.baud)
-        .open_native_async()
-        .expect("Failed to open serial io");
-
     trace!("Connecting to {}", config.server.addr);
 
-    let client = proto::running_observer_client::RunningObserverClient::connect("http://".to_owned() + config.server.addr.as_str())
-        .await
-        .unwrap();
+    // NOTE: The channel connects on demand and reconnects after the server restarted.
+    let channel = Endpoint::from_shared("http://".to_owned() + config.server.addr.as_str())
+        .unwrap_or_else(|error| panic!("Invalid server addr! {:?}", error))
+        .connect_lazy();
+    let signaler = Signaler::spawn(RunningObserverClient::new(channel));
 
-    let mut reader = LineCodec.framed(serial);
+    let mut board = StatusBoard::default();
 
-    while let Some(line_result) = reader.next().await {
-        let current_unixtime_ms = get_unixtime_ms();
-        let line = line_result.expect("Failed to read line");
+    let reader: Lines = match (args.simulate, args.script) {
+        (true, Some(script)) => {
+            let script = read_to_string(script)
+                .await
+                .unwrap_or_else(|error| panic!("Failed to load script! {:?}", error));
 
-        if line.starts_with("0") {
-            let client_cloned = client.clone();
-            tokio::spawn(async move {
-                on_signal(client_cloned, current_unixtime_ms).await;
-            });
+            simulate::script_lines(
+                simulate::parse_script(&script)
+                    .unwrap_or_else(|error| panic!("Invalid script! {:?}", error)),
+            )
         }
+        (true, None) => simulate::stdin_lines(),
+        (false, _) => {
+            let mut inputs = config.sensor.inputs;
+
+            let input = match (args.input, args.com) {
+                (Some(Input::Serial { path, .. }), _) | (None, Some(path)) => Some(Input::Serial {
+                    path,
+                    baud: args.baud,
+                }),
+                (input, _) => input,
+            };
+            if let Some(input) = input {
+                inputs.push(InputConfig {
+                    id: None,
+                    input,
+                    baud: None,
+                    role: Role::Flip,
+                    channels: Default::default(),
+                    debounce_ms: 0,
+                    protocol: config.sensor.protocol,
+                    clock: config.sensor.clock,
+                });
+            }
+
+            if inputs.is_empty() {
+                panic!("No input! Specify --input or list sensor.inputs in the config.");
+            }
+
+            let mut forwardings = inputs
+                .iter()
+                .map(|config| {
+                    let parser = LineParser::new(&config.protocol).unwrap_or_else(|error| {
+                        panic!("Invalid protocol of {}! {:?}", config.id(), error)
+                    });
+                    let status =
+                        board.register(&config.id(), &config.input().to_string(), config.role);
+
+                    let handler = Handler::new(
+                        parser,
+                        config.role,
+                        Duration::from_millis(config.debounce_ms),
+                        status,
+                    )
+                    .with_channels(config.channels.clone());
+                    (config.input(), handler)
+                })
+                .collect::<Vec<_>>();
+
+            serve_status(&config.server, &board);
+
+            join_all(inputs.iter().zip(forwardings.iter_mut()).map(
+                |(config, (input, handler))| {
+                    forward_forever(input, handler, config.clock.as_ref(), &signaler)
+                },
+            ))
+            .await;
+            return;
+        }
+    };
+
+    let parser = LineParser::new(&config.sensor.protocol)
+        .unwrap_or_else(|error| panic!("Invalid sensor protocol! {:?}", error));
+    let mut handler = Handler::new(
+        parser,
+        Role::Flip,
+        Duration::ZERO,
+        board.register("simulate", "simulate://", Role::Flip),
+    );
+
+    serve_status(&config.server, &board);
+
+    if let Err(error) = forward_triggers(reader, &mut handler, &signaler).await {
+        error!("Failed to read line {:?}", error);
     }
+
+    // NOTE: Input ends only when simulating, let the buffered signals reach the server.
+    signaler.close().await;
 }
@@ -0,0 +1,163 @@
+use anyhow::Result;
+use log::warn;
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// How lines sent by the sensor are parsed.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(tag = "format", rename_all = "snake_case")]
+pub enum ProtocolConfig {
+    /// `0` triggers, `T <ticks>` triggers with the device ticks and `C <ticks>` replies a sync
+    /// request.
+    #[default]
+    Default,
+    /// Lines matching `pattern` are events. Named groups `channel`, `event` and `ticks` are
+    /// extracted if present. `event` is looked up in `events`, and a line without it is a trigger.
+    /// `channel` selects the role of the trigger, see `InputConfig::channels`.
+    Regex {
+        pattern: String,
+        #[serde(default)]
+        events: HashMap<String, EventKind>,
+    },
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Trigger,
+    /// The reply to a sync request, see `DeviceClock`.
+    Clock,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct SensorEvent {
+    pub channel: Option<String>,
+    pub kind: EventKind,
+    /// Device tick counter, stamped when the line is read if not sent.
+    pub ticks: Option<u64>,
+}
+
+pub enum Parser {
+    Default,
+    Regex {
+        regex: Regex,
+        events: HashMap<String, EventKind>,
+    },
+}
+
+impl Parser {
+    pub fn new(config: &ProtocolConfig) -> Result<Parser> {
+        Ok(match config {
+            ProtocolConfig::Default => Parser::Default,
+            ProtocolConfig::Regex { pattern, events } => Parser::Regex {
+                regex: Regex::new(pattern)?,
+                events: events.clone(),
+            },
+        })
+    }
+
+    /// Returns `None` for lines which are not events.
+    pub fn parse(&self, line: &str) -> Option<SensorEvent> {
+        let line = line.trim_end_matches(['\r', '\n']);
+
+        match self {
+            Parser::Default => parse_default(line),
+            Parser::Regex { regex, events } => {
+                let captures = regex.captures(line)?;
+
+                let kind = match captures.name("event") {
+                    Some(event) => *events.get(event.as_str())?,
+                    None => EventKind::Trigger,
+                };
+
+                let ticks = match captures.name("ticks") {
+                    Some(ticks) => match ticks.as_str().parse::<u64>() {
+                        Ok(ticks) => Some(ticks),
+                        Err(_) => {
+                            warn!("Invalid ticks {:?} in {:?}", ticks.as_str(), line);
+                            return None;
+                        }
+                    },
+                    None => None,
+                };
+
+                Some(SensorEvent {
+                    channel: captures
+                        .name("channel")
+                        .map(|channel| channel.as_str().to_string()),
+                    kind,
+                    ticks,
+                })
+            }
+        }
+    }
+}
+
+fn parse_default(line: &str) -> Option<SensorEvent> {
+    let ticks = |ticks: &str| ticks.trim().parse::<u64>().ok();
+    let event = |kind, ticks| SensorEvent {
+        channel: None,
+        kind,
+        ticks,
+    };
+
+    if line.starts_with('0') {
+        Some(event(EventKind::Trigger, None))
+    } else if let Some(ticks) = line.strip_prefix("T ").and_then(ticks) {
+        Some(event(EventKind::Trigger, Some(ticks)))
+    } else {
+        let ticks = line.strip_prefix("C ").and_then(ticks)?;
+        Some(event(EventKind::Clock, Some(ticks)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EventKind, Parser, ProtocolConfig, SensorEvent};
+
+    fn event(channel: Option<&str>, kind: EventKind, ticks: Option<u64>) -> Option<SensorEvent> {
+        Some(SensorEvent {
+            channel: channel.map(ToString::to_string),
+            kind,
+            ticks,
+        })
+    }
+
+    #[test]
+    fn parses_default_lines() {
+        let parser = Parser::new(&ProtocolConfig::Default).unwrap();
+
+        assert_eq!(parser.parse("0\r\n"), event(None, EventKind::Trigger, None));
+        assert_eq!(
+            parser.parse("T 123\r\n"),
+            event(None, EventKind::Trigger, Some(123))
+        );
+        assert_eq!(
+            parser.parse("C 456\n"),
+            event(None, EventKind::Clock, Some(456))
+        );
+        assert_eq!(parser.parse("T abc\n"), None);
+        assert_eq!(parser.parse("1\n"), None);
+    }
+
+    #[test]
+    fn parses_regex_lines() {
+        let config: ProtocolConfig = serde_json::from_str(
+            r#"{
+                "format": "regex",
+                "pattern": "^(?P<channel>c\\d) (?P<event>[SF]) (?P<ticks>\\d+)$",
+                "events": {"S": "trigger", "F": "trigger"}
+            }"#,
+        )
+        .unwrap();
+        let parser = Parser::new(&config).unwrap();
+
+        assert_eq!(
+            parser.parse("c1 F 98765\r\n"),
+            event(Some("c1"), EventKind::Trigger, Some(98765))
+        );
+        assert_eq!(parser.parse("c1 X 98765\n"), None);
+        assert_eq!(parser.parse("garbage\n"), None);
+    }
+}
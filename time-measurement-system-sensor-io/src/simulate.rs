@@ -0,0 +1,76 @@
+use anyhow::{bail, Context, Result};
+use log::trace;
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::Lines;
+
+/// The line the sensor sends when the beam is broken.
+const TRIGGER_LINE: &str = "0\n";
+
+/// Parses a simulation script. Each line is the offset in milliseconds from the start of the
+/// script at which the sensor triggers. Empty lines and lines starting with `#` are ignored.
+pub fn parse_script(script: &str) -> Result<Vec<u64>> {
+    let mut offsets: Vec<u64> = Vec::new();
+
+    for (index, line) in script.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let offset = line
+            .parse::<u64>()
+            .with_context(|| format!("Invalid offset {:?} at line {}", line, index + 1))?;
+
+        if offsets.last().is_some_and(|last| *last > offset) {
+            bail!("Offsets must not decrease but line {} does", index + 1);
+        }
+
+        offsets.push(offset);
+    }
+
+    Ok(offsets)
+}
+
+/// Emits a trigger line at each offset of the script.
+pub fn script_lines(offsets: Vec<u64>) -> Lines {
+    let (tx, rx) = tokio::sync::mpsc::channel(1);
+
+    tokio::spawn(async move {
+        let started_at = tokio::time::Instant::now();
+
+        for offset in offsets {
+            tokio::time::sleep_until(started_at + std::time::Duration::from_millis(offset)).await;
+            trace!("Simulating a trigger at {}ms", offset);
+
+            if tx.send(Ok(TRIGGER_LINE.to_string())).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    Box::pin(ReceiverStream::new(rx))
+}
+
+/// Passes lines typed in stdin as if the sensor sent them, so typing `0` triggers.
+pub fn stdin_lines() -> Lines {
+    crate::lines(tokio::io::stdin())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_script;
+
+    #[test]
+    fn parses_script() {
+        let offsets = parse_script("# start\n0\n\n 30500 \n30500\n").unwrap();
+
+        assert_eq!(offsets, vec![0, 30500, 30500]);
+    }
+
+    #[test]
+    fn fails_when_offsets_decrease() {
+        parse_script("100\n50\n").unwrap_err();
+        parse_script("abc\n").unwrap_err();
+    }
+}
@@ -0,0 +1,240 @@
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+
+use crate::trigger::Role;
+
+/// Raw trigger events kept for slow subscribers.
+pub const EVENT_CAPACITY: usize = 256;
+
+/// Timestamps are microseconds since the unix epoch.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InputStatus {
+    /// Whether the port is open or the socket is connected.
+    pub open: bool,
+    /// Triggers signaled since the process started.
+    pub triggers: u64,
+    /// Triggers dropped by debouncing since the process started.
+    pub debounced: u64,
+    pub last_trigger_at: Option<i64>,
+    /// Why the input was closed last time.
+    pub last_error: Option<(i64, String)>,
+}
+
+/// A trigger as read from an input, whether it was signaled or not. Timestamps are microseconds
+/// since the unix epoch.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TriggerEvent {
+    pub input_id: String,
+    /// Without the line ending.
+    pub line: String,
+    pub received_at: i64,
+    /// Stamped by the device clock if it is synced, otherwise `received_at`.
+    pub timestamp: i64,
+    pub debounced: bool,
+}
+
+/// Shared between the forwarding of an input and the status endpoint.
+#[derive(Clone)]
+pub struct StatusHandle {
+    id: String,
+    status: Arc<Mutex<InputStatus>>,
+    events: broadcast::Sender<TriggerEvent>,
+}
+
+/// Reports to nowhere, e.g. for simulation.
+impl Default for StatusHandle {
+    fn default() -> Self {
+        StatusHandle {
+            id: String::new(),
+            status: Default::default(),
+            events: broadcast::channel(1).0,
+        }
+    }
+}
+
+impl StatusHandle {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn get(&self) -> InputStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    pub(crate) fn set_open(&self, open: bool) {
+        self.status.lock().unwrap().open = open;
+    }
+
+    pub(crate) fn failed(&self, at: i64, error: String) {
+        self.status.lock().unwrap().last_error = Some((at, error));
+    }
+
+    pub(crate) fn triggered(&self, event: TriggerEvent) {
+        {
+            let mut status = self.status.lock().unwrap();
+            if event.debounced {
+                status.debounced += 1;
+            } else {
+                status.triggers += 1;
+                status.last_trigger_at = Some(event.timestamp);
+            }
+        }
+
+        // NOTE: Fails only when nobody subscribes.
+        let _ = self.events.send(event);
+    }
+}
+
+#[derive(Clone)]
+pub struct Entry {
+    pub id: String,
+    /// e.g. `serial://COM3`
+    pub input: String,
+    pub role: Role,
+    pub status: StatusHandle,
+}
+
+/// Inputs reported by the status endpoint.
+#[derive(Clone)]
+pub struct StatusBoard {
+    entries: Vec<Entry>,
+    events: broadcast::Sender<TriggerEvent>,
+}
+
+impl Default for StatusBoard {
+    fn default() -> Self {
+        StatusBoard {
+            entries: Vec::new(),
+            events: broadcast::channel(EVENT_CAPACITY).0,
+        }
+    }
+}
+
+impl StatusBoard {
+    pub fn register(&mut self, id: &str, input: &str, role: Role) -> StatusHandle {
+        let status = StatusHandle {
+            id: id.to_string(),
+            status: Default::default(),
+            events: self.events.clone(),
+        };
+
+        self.entries.push(Entry {
+            id: id.to_string(),
+            input: input.to_string(),
+            role,
+            status: status.clone(),
+        });
+
+        status
+    }
+
+    pub fn entries(&self) -> &Vec<Entry> {
+        &self.entries
+    }
+
+    /// Trigger events of all inputs from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<TriggerEvent> {
+        self.events.subscribe()
+    }
+}
+
+pub mod server {
+    use async_trait::async_trait;
+    use log::warn;
+    use std::pin::Pin;
+    use tokio::sync::broadcast::error::RecvError;
+    use tokio_stream::Stream;
+    use tonic::{Request, Response, Status};
+
+    use super::{Entry, StatusBoard, TriggerEvent};
+    use crate::proto::sensor_io as proto;
+    use crate::timestamp_to_proto;
+    use crate::trigger::Role;
+
+    impl From<Role> for proto::Role {
+        fn from(role: Role) -> Self {
+            match role {
+                Role::Flip => proto::Role::Flip,
+                Role::Start => proto::Role::Start,
+                Role::Stop => proto::Role::Stop,
+            }
+        }
+    }
+
+    impl From<&Entry> for proto::Input {
+        fn from(entry: &Entry) -> Self {
+            let status = entry.status.get();
+
+            proto::Input {
+                id: entry.id.clone(),
+                input: entry.input.clone(),
+                role: proto::Role::from(entry.role) as i32,
+                open: status.open,
+                triggers: status.triggers,
+                last_trigger: status.last_trigger_at.map(timestamp_to_proto),
+                debounced: status.debounced,
+                last_error: status
+                    .last_error
+                    .as_ref()
+                    .map(|(_, error)| error.clone())
+                    .unwrap_or_default(),
+                last_error_at: status.last_error.map(|(at, _)| timestamp_to_proto(at)),
+            }
+        }
+    }
+
+    impl From<TriggerEvent> for proto::TriggerEvent {
+        fn from(event: TriggerEvent) -> Self {
+            proto::TriggerEvent {
+                input_id: event.input_id,
+                line: event.line,
+                received_at: Some(timestamp_to_proto(event.received_at)),
+                time: Some(timestamp_to_proto(event.timestamp)),
+                debounced: event.debounced,
+            }
+        }
+    }
+
+    #[async_trait]
+    impl proto::sensor_io_server::SensorIo for StatusBoard {
+        type SubscribeTriggersStream =
+            Pin<Box<dyn Stream<Item = Result<proto::TriggerEvent, Status>> + Send>>;
+
+        async fn status(
+            &self,
+            _request: Request<proto::StatusRequest>,
+        ) -> Result<Response<proto::StatusReply>, Status> {
+            Ok(Response::new(proto::StatusReply {
+                inputs: self.entries.iter().map(Into::into).collect(),
+            }))
+        }
+
+        async fn subscribe_triggers(
+            &self,
+            _request: Request<proto::SubscribeTriggersRequest>,
+        ) -> Result<Response<Self::SubscribeTriggersStream>, Status> {
+            let (tx, rx) = tokio::sync::mpsc::channel(1);
+            let mut events = self.subscribe();
+            tokio::spawn(async move {
+                loop {
+                    let event = match events.recv().await {
+                        Ok(event) => event,
+                        Err(RecvError::Lagged(count)) => {
+                            warn!("A trigger subscriber missed {} events", count);
+                            continue;
+                        }
+                        Err(RecvError::Closed) => break,
+                    };
+
+                    if tx.send(Ok(event.into())).await.is_err() {
+                        break;
+                    }
+                }
+            });
+
+            Ok(Response::new(Box::pin(
+                tokio_stream::wrappers::ReceiverStream::new(rx),
+            )))
+        }
+    }
+}
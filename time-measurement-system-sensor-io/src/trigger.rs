@@ -0,0 +1,343 @@
+use futures::stream::StreamExt;
+use log::{debug, error, trace, warn};
+use serde::Deserialize;
+use std::{collections::HashMap, error::Error, io, time::Duration};
+use tokio::{
+    io::{AsyncWrite, AsyncWriteExt},
+    sync::mpsc,
+    task::JoinHandle,
+};
+use tonic::{transport::Channel, Code, Status};
+
+use crate::{
+    backoff::Backoff,
+    clock::{ClockConfig, DeviceClock},
+    debounce::Debouncer,
+    lines,
+    proto::running_observer::{
+        running_observer_client::RunningObserverClient, source::Kind,
+        FlipRunningStateCommandRequest, Source, StartCommandRequest, StopCommandRequest,
+    },
+    protocol::{EventKind, Parser},
+    status::{StatusHandle, TriggerEvent},
+    timestamp_to_proto, ByteSource, Lines,
+};
+
+/// Asks the device to reply its tick counter as `C <ticks>`.
+const SYNC_REQUEST: &[u8] = b"SYNC\n";
+
+pub(crate) fn get_unixtime_us() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_micros() as i64
+}
+
+/// What a trigger of an input means to the server.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Stops the running car, or starts one if none is running. For a gate at both lines.
+    #[default]
+    Flip,
+    /// Starts the head of the queue. For a gate at the start line.
+    Start,
+    /// Stops the car which started first. For a gate at the finish line.
+    Stop,
+}
+
+#[derive(Debug)]
+struct Signal {
+    role: Role,
+    input_id: String,
+    timestamp: i64,
+}
+
+/// Sends signals to the server one by one, so the server sees them in order even when they come
+/// from different inputs. Signals are buffered with their timestamps while the server is
+/// unreachable. Timestamps are microseconds since the unix epoch.
+///
+/// A signal is sent again if it failed in transport, e.g. the server being unreachable or going
+/// down meanwhile. Only signals rejected by the server are dropped.
+pub struct Signaler {
+    sender: mpsc::UnboundedSender<Signal>,
+    task: JoinHandle<()>,
+}
+
+impl Signaler {
+    pub fn spawn(mut client: RunningObserverClient<Channel>) -> Signaler {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+
+        let task = tokio::spawn(async move {
+            let mut backoff = Backoff::default();
+
+            while let Some(signal) = receiver.recv().await {
+                while !on_signal(&mut client, &signal).await {
+                    tokio::time::sleep(backoff.next_delay()).await;
+                }
+                backoff.reset();
+            }
+        });
+
+        Signaler { sender, task }
+    }
+
+    /// `input_id` tells the server which sensor the signal came from.
+    pub fn signal(&self, role: Role, input_id: &str, timestamp: i64) {
+        // NOTE: Fails only after the task panicked.
+        let _ = self.sender.send(Signal {
+            role,
+            input_id: input_id.to_string(),
+            timestamp,
+        });
+    }
+
+    /// Waits until the buffered signals are delivered.
+    pub async fn close(self) {
+        drop(self.sender);
+        let _ = self.task.await;
+    }
+}
+
+/// Returns `false` if the signal should be sent again.
+async fn on_signal(client: &mut RunningObserverClient<Channel>, signal: &Signal) -> bool {
+    debug!("Signaling! {:?}", signal);
+    let Signal {
+        role,
+        input_id,
+        timestamp,
+    } = signal;
+    let millis = timestamp.div_euclid(1_000);
+    let time = Some(timestamp_to_proto(*timestamp));
+    let source = Some(Source {
+        kind: Kind::Sensor as i32,
+        sensor_id: input_id.clone(),
+    });
+
+    let result = match role {
+        Role::Flip => {
+            client
+                .flip_running_state(FlipRunningStateCommandRequest {
+                    timestamp: millis,
+                    time,
+                    source,
+                })
+                .await
+        }
+        Role::Start => {
+            client
+                .start(StartCommandRequest {
+                    timestamp: millis,
+                    pending_car_id: None,
+                    time,
+                    source,
+                })
+                .await
+        }
+        Role::Stop => {
+            client
+                .stop(StopCommandRequest {
+                    timestamp: millis,
+                    id: None,
+                    time,
+                    source,
+                })
+                .await
+        }
+    };
+
+    match result {
+        Ok(_) => true,
+        Err(e) if is_transport_error(&e) => {
+            warn!(
+                "Failed to reach the server, retrying {} later. {:?}",
+                timestamp, e
+            );
+            false
+        }
+        Err(e) => {
+            error!("Failed to signal {:?} {:?}", role, e);
+            true
+        }
+    }
+}
+
+/// Whether the request failed in transport rather than being rejected by the server.
+fn is_transport_error(status: &Status) -> bool {
+    match status.code() {
+        Code::Unavailable => true,
+        Code::Unknown => {
+            let mut source = status.source();
+
+            while let Some(error) = source {
+                if error.is::<hyper::Error>() {
+                    return true;
+                }
+                source = error.source();
+            }
+
+            false
+        }
+        _ => false,
+    }
+}
+
+/// Turns the lines of an input into signals.
+pub struct Handler {
+    parser: Parser,
+    role: Role,
+    /// Roles by the channel of events. `role` is used if empty.
+    channels: HashMap<String, Role>,
+    debounce: Duration,
+    /// By channel, so that gates sharing an input do not drop each other's triggers.
+    debouncers: HashMap<Option<String>, Debouncer>,
+    status: StatusHandle,
+}
+
+impl Handler {
+    pub fn new(parser: Parser, role: Role, debounce: Duration, status: StatusHandle) -> Handler {
+        Handler {
+            parser,
+            role,
+            channels: HashMap::new(),
+            debounce,
+            debouncers: HashMap::new(),
+            status,
+        }
+    }
+
+    /// Signals triggers of these channels with their roles and ignores the others, for a device
+    /// reporting several gates over one input.
+    pub fn with_channels(mut self, channels: HashMap<String, Role>) -> Handler {
+        self.channels = channels;
+        self
+    }
+
+    pub fn status(&self) -> &StatusHandle {
+        &self.status
+    }
+
+    fn handle_line(
+        &mut self,
+        line: &str,
+        received_at: i64,
+        clock: Option<&mut DeviceClock>,
+        signaler: &Signaler,
+    ) {
+        let Some(event) = self.parser.parse(line) else {
+            trace!("Ignoring line {:?}", line);
+            return;
+        };
+        trace!("Received {:?}", event);
+
+        let timestamp = match (event.kind, event.ticks, clock) {
+            (EventKind::Trigger, None, _) => received_at,
+            (EventKind::Trigger, Some(ticks), Some(clock)) => match clock.to_unixtime_us(ticks) {
+                Some(timestamp) => timestamp,
+                None => {
+                    warn!("Device clock is not synced yet, stamping {} on read", ticks);
+                    received_at
+                }
+            },
+            (EventKind::Trigger, Some(ticks), None) => {
+                warn!("No device clock configured, stamping {} on read", ticks);
+                received_at
+            }
+            (EventKind::Clock, Some(ticks), Some(clock)) => {
+                return clock.synced(ticks, received_at);
+            }
+            (EventKind::Clock, _, _) => {
+                trace!("Ignoring clock {:?}", line);
+                return;
+            }
+        };
+
+        let role = if self.channels.is_empty() {
+            self.role
+        } else {
+            match event
+                .channel
+                .as_ref()
+                .and_then(|channel| self.channels.get(channel))
+            {
+                Some(role) => *role,
+                None => {
+                    trace!("Ignoring trigger of channel {:?}", event.channel);
+                    return;
+                }
+            }
+        };
+
+        let debounced = !self
+            .debouncers
+            .entry(event.channel)
+            .or_insert_with(|| Debouncer::new(self.debounce))
+            .accept(timestamp);
+        self.status.triggered(TriggerEvent {
+            input_id: self.status.id().to_string(),
+            line: line.trim_end_matches(['\r', '\n']).to_string(),
+            received_at,
+            timestamp,
+            debounced,
+        });
+
+        if debounced {
+            debug!("Debounced a trigger at {}", timestamp);
+            return;
+        }
+        signaler.signal(role, self.status.id(), timestamp);
+    }
+}
+
+/// A flipping input without debouncing, e.g. for simulation.
+impl From<Parser> for Handler {
+    fn from(parser: Parser) -> Self {
+        Handler::new(parser, Role::Flip, Duration::ZERO, StatusHandle::default())
+    }
+}
+
+/// Signals for each trigger line until `lines` ends.
+pub async fn forward_triggers(
+    mut lines: Lines,
+    handler: &mut Handler,
+    signaler: &Signaler,
+) -> io::Result<()> {
+    while let Some(line) = lines.next().await {
+        let current_unixtime_us = get_unixtime_us();
+
+        handler.handle_line(&line?, current_unixtime_us, None, signaler);
+    }
+
+    Ok(())
+}
+
+/// Same as `forward_triggers` but for a device stamping triggers with its own ticks, syncing the
+/// device clock periodically.
+pub async fn forward_synced_triggers<S: ByteSource + AsyncWrite>(
+    source: S,
+    handler: &mut Handler,
+    config: &ClockConfig,
+    signaler: &Signaler,
+) -> io::Result<()> {
+    let (reader, mut writer) = tokio::io::split(source);
+    let mut lines = lines(reader);
+    let mut clock = DeviceClock::new(config);
+    let mut sync = tokio::time::interval(std::time::Duration::from_millis(config.sync_interval_ms));
+
+    loop {
+        tokio::select! {
+            _ = sync.tick() => {
+                clock.sync_requested(get_unixtime_us());
+                writer.write_all(SYNC_REQUEST).await?;
+            }
+            line = lines.next() => {
+                let Some(line) = line else {
+                    return Ok(());
+                };
+                let current_unixtime_us = get_unixtime_us();
+
+                handler.handle_line(&line?, current_unixtime_us, Some(&mut clock), signaler);
+            }
+        }
+    }
+}
@@ -0,0 +1,202 @@
+// NOTE: Each test crate uses a part of this.
+#![allow(dead_code)]
+
+use async_trait::async_trait;
+use std::{
+    net::SocketAddr,
+    pin::Pin,
+    sync::{Arc, Mutex},
+};
+use tokio_stream::{wrappers::TcpListenerStream, Stream};
+use tonic::{transport::Endpoint, Request, Response, Status};
+
+use time_measurement_system_sensor_io::{
+    proto::running_observer::{
+        self as proto,
+        running_observer_client::RunningObserverClient,
+        running_observer_server::{RunningObserver, RunningObserverServer},
+    },
+    trigger::Signaler,
+};
+
+/// Records the timestamps of calls in microseconds instead of observing cars.
+#[derive(Clone, Default)]
+pub struct StandIn {
+    pub flips: Arc<Mutex<Vec<i64>>>,
+    pub starts: Arc<Mutex<Vec<i64>>>,
+    pub stops: Arc<Mutex<Vec<i64>>>,
+    /// Sensor ids of all calls.
+    pub sources: Arc<Mutex<Vec<String>>>,
+    /// Records calls but never replies, like a server hanging until it is killed.
+    pub stalls: bool,
+}
+
+impl StandIn {
+    fn record_source(&self, source: Option<proto::Source>) {
+        let source = source.unwrap();
+
+        assert_eq!(source.kind(), proto::source::Kind::Sensor);
+        self.sources.lock().unwrap().push(source.sensor_id);
+    }
+
+    async fn reply(&self) -> Result<Response<proto::CommandReply>, Status> {
+        if self.stalls {
+            std::future::pending::<()>().await;
+        }
+        Ok(Response::new(proto::CommandReply {}))
+    }
+}
+
+/// Microseconds since the unix epoch, checking the legacy milliseconds match.
+fn to_micros(timestamp: i64, time: Option<prost_types::Timestamp>) -> i64 {
+    let time = time.unwrap();
+
+    assert_eq!(
+        timestamp,
+        time.seconds * 1_000 + time.nanos as i64 / 1_000_000
+    );
+    time.seconds * 1_000_000 + time.nanos as i64 / 1_000
+}
+
+type ReplyStream<T> = Pin<Box<dyn Stream<Item = Result<T, Status>> + Send>>;
+
+#[async_trait]
+impl RunningObserver for StandIn {
+    type SubscribeChangeStream = ReplyStream<proto::ReadAllReply>;
+    type SubscribeDeltaStream = ReplyStream<proto::DeltaReply>;
+
+    async fn flip_running_state(
+        &self,
+        request: Request<proto::FlipRunningStateCommandRequest>,
+    ) -> Result<Response<proto::CommandReply>, Status> {
+        let request = request.into_inner();
+        self.record_source(request.source);
+
+        self.flips
+            .lock()
+            .unwrap()
+            .push(to_micros(request.timestamp, request.time));
+        self.reply().await
+    }
+
+    async fn start(
+        &self,
+        request: Request<proto::StartCommandRequest>,
+    ) -> Result<Response<proto::CommandReply>, Status> {
+        let request = request.into_inner();
+        self.record_source(request.source);
+
+        self.starts
+            .lock()
+            .unwrap()
+            .push(to_micros(request.timestamp, request.time));
+        self.reply().await
+    }
+
+    async fn stop(
+        &self,
+        request: Request<proto::StopCommandRequest>,
+    ) -> Result<Response<proto::CommandReply>, Status> {
+        let request = request.into_inner();
+        self.record_source(request.source);
+
+        self.stops
+            .lock()
+            .unwrap()
+            .push(to_micros(request.timestamp, request.time));
+        self.reply().await
+    }
+
+    async fn cancel(
+        &self,
+        _request: Request<proto::CancelCommandRequest>,
+    ) -> Result<Response<proto::CommandReply>, Status> {
+        Err(Status::unimplemented("cancel"))
+    }
+
+    async fn set_course_state(
+        &self,
+        _request: Request<proto::SetCourseStateCommandRequest>,
+    ) -> Result<Response<proto::CommandReply>, Status> {
+        Err(Status::unimplemented("set_course_state"))
+    }
+
+    async fn update_metadata(
+        &self,
+        _request: Request<proto::UpdateMetadataCommandRequest>,
+    ) -> Result<Response<proto::CommandReply>, Status> {
+        Err(Status::unimplemented("update_metadata"))
+    }
+
+    async fn read_all(
+        &self,
+        _request: Request<proto::ReadAllRequest>,
+    ) -> Result<Response<proto::ReadAllReply>, Status> {
+        Err(Status::unimplemented("read_all"))
+    }
+
+    async fn subscribe_change(
+        &self,
+        _request: Request<proto::SubscribeChangeRequest>,
+    ) -> Result<Response<Self::SubscribeChangeStream>, Status> {
+        Err(Status::unimplemented("subscribe_change"))
+    }
+
+    async fn subscribe_delta(
+        &self,
+        _request: Request<proto::SubscribeDeltaRequest>,
+    ) -> Result<Response<Self::SubscribeDeltaStream>, Status> {
+        Err(Status::unimplemented("subscribe_delta"))
+    }
+}
+
+pub async fn serve(stand_in: StandIn) -> SocketAddr {
+    serve_on("127.0.0.1:0".parse().unwrap(), stand_in).await
+}
+
+pub async fn serve_on(addr: SocketAddr, stand_in: StandIn) -> SocketAddr {
+    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(
+        tonic::transport::Server::builder()
+            .add_service(RunningObserverServer::new(stand_in))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+
+    addr
+}
+
+/// Serves on a runtime of its own. Dropping the runtime kills the server with its connections, as
+/// a crash would.
+pub fn serve_killable(stand_in: StandIn) -> (SocketAddr, tokio::runtime::Runtime) {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    listener.set_nonblocking(true).unwrap();
+
+    runtime.spawn(async move {
+        let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+
+        tonic::transport::Server::builder()
+            .add_service(RunningObserverServer::new(stand_in))
+            .serve_with_incoming(TcpListenerStream::new(listener))
+            .await
+    });
+
+    (addr, runtime)
+}
+
+pub fn signaler(addr: SocketAddr) -> Signaler {
+    let _ = env_logger::builder().is_test(true).try_init();
+
+    let channel = Endpoint::from_shared(format!("http://{}", addr))
+        .unwrap()
+        .connect_lazy();
+    Signaler::spawn(RunningObserverClient::new(channel))
+}
@@ -0,0 +1,194 @@
+use std::time::Duration;
+use tokio::{io::AsyncWriteExt, net::TcpListener};
+use tokio_stream::StreamExt;
+use tonic::Request;
+
+use time_measurement_system_sensor_io::{
+    input::{forward_input, Input},
+    proto::sensor_io::{self, sensor_io_server::SensorIo as _},
+    protocol::{Parser as LineParser, ProtocolConfig},
+    status::StatusBoard,
+    trigger::{Handler, Role},
+};
+
+use common::{serve, signaler, StandIn};
+
+mod common;
+
+#[tokio::test]
+async fn inputs_are_forwarded_concurrently_with_their_roles() {
+    let stand_in = StandIn::default();
+    let signaler = signaler(serve(stand_in.clone()).await);
+
+    let start_gate = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let finish_gate = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let start = Input::Tcp(start_gate.local_addr().unwrap().to_string());
+    let finish = Input::Tcp(finish_gate.local_addr().unwrap().to_string());
+
+    let mut board = StatusBoard::default();
+    let mut start_handler = Handler::new(
+        LineParser::Default,
+        Role::Start,
+        Duration::ZERO,
+        board.register("start", &start.to_string(), Role::Start),
+    );
+    let mut finish_handler = Handler::new(
+        LineParser::Default,
+        Role::Stop,
+        Duration::from_secs(60),
+        board.register("finish", &finish.to_string(), Role::Stop),
+    );
+
+    let forwarding = tokio::spawn(async move {
+        let (start_result, finish_result) = tokio::join!(
+            forward_input(&start, &mut start_handler, None, &signaler),
+            forward_input(&finish, &mut finish_handler, None, &signaler),
+        );
+        signaler.close().await;
+        start_result.and(finish_result)
+    });
+
+    let (mut start_stream, _) = start_gate.accept().await.unwrap();
+    let (mut finish_stream, _) = finish_gate.accept().await.unwrap();
+
+    // NOTE: The gate may accept before the forwarding notices it is connected.
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while !board.entries().iter().all(|entry| entry.status.get().open) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+
+    let mut triggers = board
+        .subscribe_triggers(Request::new(sensor_io::SubscribeTriggersRequest {}))
+        .await
+        .unwrap()
+        .into_inner();
+
+    start_stream.write_all(b"0\n0\n").await.unwrap();
+    // NOTE: The photocell chatters while the car passes.
+    finish_stream.write_all(b"0\r\n0\n0\n").await.unwrap();
+    drop(start_stream);
+    drop(finish_stream);
+
+    forwarding.await.unwrap().unwrap();
+
+    assert_eq!(stand_in.starts.lock().unwrap().len(), 2);
+    assert_eq!(stand_in.stops.lock().unwrap().len(), 1);
+    assert!(stand_in.flips.lock().unwrap().is_empty());
+    let mut sources = stand_in.sources.lock().unwrap().clone();
+    sources.sort();
+    assert_eq!(sources, vec!["finish", "start", "start"]);
+
+    let reply = board
+        .status(Request::new(sensor_io::StatusRequest {}))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(reply.inputs.len(), 2);
+    let finish = &reply.inputs[1];
+    assert_eq!(finish.id, "finish");
+    assert_eq!(finish.role(), sensor_io::Role::Stop);
+    assert!(!finish.open);
+    assert_eq!(finish.triggers, 1);
+    assert_eq!(finish.debounced, 2);
+    assert!(finish.last_trigger.is_some());
+    assert_eq!(finish.last_error, "");
+
+    let mut finish_events = Vec::new();
+    while let Some(event) = triggers.next().await {
+        let event = event.unwrap();
+        if event.input_id == "finish" {
+            finish_events.push(event);
+        }
+        if finish_events.len() == 3 {
+            break;
+        }
+    }
+    assert_eq!(finish_events[0].line, "0");
+    assert_eq!(
+        finish_events
+            .iter()
+            .map(|event| event.debounced)
+            .collect::<Vec<_>>(),
+        vec![false, true, true]
+    );
+}
+
+#[tokio::test]
+async fn channels_of_one_input_have_their_roles() {
+    let stand_in = StandIn::default();
+    let signaler = signaler(serve(stand_in.clone()).await);
+
+    let gate = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let input = Input::Tcp(gate.local_addr().unwrap().to_string());
+
+    let protocol: ProtocolConfig =
+        serde_json::from_str(r#"{"format": "regex", "pattern": "^(?P<channel>c\\d) S$"}"#).unwrap();
+    let mut handler = Handler::new(
+        LineParser::new(&protocol).unwrap(),
+        Role::Flip,
+        Duration::from_secs(60),
+        StatusBoard::default().register("gate", &input.to_string(), Role::Flip),
+    )
+    .with_channels(
+        [
+            ("c1".to_string(), Role::Start),
+            ("c2".to_string(), Role::Stop),
+        ]
+        .into_iter()
+        .collect(),
+    );
+
+    let forwarding = tokio::spawn(async move {
+        let result = forward_input(&input, &mut handler, None, &signaler).await;
+        signaler.close().await;
+        result
+    });
+
+    let (mut stream, _) = gate.accept().await.unwrap();
+    // NOTE: Channels are debounced separately, and unknown ones are ignored.
+    stream.write_all(b"c1 S\nc2 S\nc3 S\nc2 S\n").await.unwrap();
+    drop(stream);
+
+    forwarding.await.unwrap().unwrap();
+
+    assert_eq!(stand_in.starts.lock().unwrap().len(), 1);
+    assert_eq!(stand_in.stops.lock().unwrap().len(), 1);
+    assert!(stand_in.flips.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn failures_are_reported() {
+    let stand_in = StandIn::default();
+    let signaler = signaler(serve(stand_in.clone()).await);
+
+    // NOTE: Reserves a port nobody listens on.
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let input = Input::Tcp(addr.to_string());
+
+    let mut board = StatusBoard::default();
+    let mut handler = Handler::new(
+        LineParser::Default,
+        Role::Flip,
+        Duration::ZERO,
+        board.register("gate", &input.to_string(), Role::Flip),
+    );
+
+    forward_input(&input, &mut handler, None, &signaler)
+        .await
+        .unwrap_err();
+
+    let reply = board
+        .status(Request::new(sensor_io::StatusRequest {}))
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(!reply.inputs[0].open);
+    assert_ne!(reply.inputs[0].last_error, "");
+    assert!(reply.inputs[0].last_error_at.is_some());
+}
@@ -0,0 +1,78 @@
+use std::time::Duration;
+use tokio::{
+    io::AsyncWriteExt,
+    net::{TcpListener, UdpSocket},
+};
+
+use time_measurement_system_sensor_io::{
+    input::{forward_input, udp_lines, Input},
+    protocol::Parser as LineParser,
+    trigger::{forward_triggers, Handler},
+};
+
+use common::{serve, signaler, StandIn};
+
+mod common;
+
+#[tokio::test]
+async fn tcp_input_is_forwarded() {
+    let stand_in = StandIn::default();
+    let signaler = signaler(serve(stand_in.clone()).await);
+
+    let gate = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let input = Input::Tcp(gate.local_addr().unwrap().to_string());
+
+    let forwarding = tokio::spawn(async move {
+        let result = forward_input(
+            &input,
+            &mut Handler::from(LineParser::Default),
+            None,
+            &signaler,
+        )
+        .await;
+        signaler.close().await;
+        result
+    });
+
+    let (mut stream, _) = gate.accept().await.unwrap();
+    stream.write_all(b"0\n1\n0").await.unwrap();
+    stream.write_all(b"\n").await.unwrap();
+    drop(stream);
+
+    forwarding.await.unwrap().unwrap();
+
+    assert_eq!(stand_in.flips.lock().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn udp_datagrams_end_lines() {
+    let stand_in = StandIn::default();
+    let signaler = signaler(serve(stand_in.clone()).await);
+
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = socket.local_addr().unwrap();
+
+    let forwarding = tokio::spawn(async move {
+        let _ = forward_triggers(
+            udp_lines(socket),
+            &mut Handler::from(LineParser::Default),
+            &signaler,
+        )
+        .await;
+    });
+
+    let gate = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    gate.send_to(b"0", addr).await.unwrap();
+    gate.send_to(b"1\n0\r\n0\n", addr).await.unwrap();
+
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while stand_in.flips.lock().unwrap().len() < 3 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+    forwarding.abort();
+
+    assert_eq!(stand_in.flips.lock().unwrap().len(), 3);
+}
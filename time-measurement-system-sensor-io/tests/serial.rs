@@ -0,0 +1,197 @@
+use std::net::SocketAddr;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, DuplexStream};
+use tokio::task::JoinHandle;
+
+use time_measurement_system_sensor_io::{
+    clock::ClockConfig,
+    lines,
+    protocol::Parser as LineParser,
+    trigger::{forward_synced_triggers, forward_triggers, Handler},
+};
+
+use common::{serve, serve_killable, serve_on, signaler, StandIn};
+
+mod common;
+
+/// Returns the device side of the line, and the forwarding which ends when the device is dropped.
+async fn setup(stand_in: &StandIn) -> (DuplexStream, JoinHandle<std::io::Result<()>>) {
+    let addr = serve(stand_in.clone()).await;
+    setup_with(addr)
+}
+
+fn setup_with(addr: SocketAddr) -> (DuplexStream, JoinHandle<std::io::Result<()>>) {
+    let signaler = signaler(addr);
+
+    let (device, host) = tokio::io::duplex(64);
+
+    (
+        device,
+        tokio::spawn(async move {
+            let result = forward_triggers(
+                lines(host),
+                &mut Handler::from(LineParser::Default),
+                &signaler,
+            )
+            .await;
+            signaler.close().await;
+            result
+        }),
+    )
+}
+
+#[tokio::test]
+async fn partial_lines_trigger_once_completed() {
+    let stand_in = StandIn::default();
+    let (mut device, forwarding) = setup(&stand_in).await;
+
+    device.write_all(b"0").await.unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    device.write_all(b"\n1\n0").await.unwrap();
+    drop(device);
+
+    forwarding.await.unwrap().unwrap();
+
+    // NOTE: The last line never completes so it is not a trigger.
+    assert_eq!(stand_in.flips.lock().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn invalid_utf8_is_skipped() {
+    let stand_in = StandIn::default();
+    let (mut device, forwarding) = setup(&stand_in).await;
+
+    device.write_all(b"0\xff\xfe\n0\n").await.unwrap();
+    drop(device);
+
+    forwarding.await.unwrap().unwrap();
+
+    assert_eq!(stand_in.flips.lock().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn bursts_are_signaled_in_order() {
+    let stand_in = StandIn::default();
+    let (mut device, forwarding) = setup(&stand_in).await;
+
+    for _ in 0..20 {
+        device.write_all(b"0\r\n1\r\n").await.unwrap();
+    }
+    drop(device);
+
+    forwarding.await.unwrap().unwrap();
+
+    let flips = stand_in.flips.lock().unwrap();
+    assert_eq!(flips.len(), 20);
+    assert!(flips.windows(2).all(|pair| pair[0] <= pair[1]));
+}
+
+#[tokio::test]
+async fn triggers_are_buffered_while_server_is_down() {
+    // NOTE: Reserves a port nobody listens on until the server is up.
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+
+    let stand_in = StandIn::default();
+    let (mut device, forwarding) = setup_with(addr);
+
+    device.write_all(b"0\n").await.unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    device.write_all(b"0\n").await.unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+
+    let server_started_at = std::time::SystemTime::now()
+        .duration_since(std::time::SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_micros() as i64;
+    serve_on(addr, stand_in.clone()).await;
+
+    drop(device);
+    forwarding.await.unwrap().unwrap();
+
+    let flips = stand_in.flips.lock().unwrap();
+    assert_eq!(flips.len(), 2);
+    assert!(flips[0] < flips[1]);
+    assert!(flips[1] < server_started_at);
+}
+
+#[tokio::test]
+async fn triggers_are_resent_when_server_dies_mid_request() {
+    let stalling = StandIn {
+        stalls: true,
+        ..StandIn::default()
+    };
+    let (addr, server) = serve_killable(stalling.clone());
+
+    let stand_in = StandIn::default();
+    let (mut device, forwarding) = setup_with(addr);
+
+    device.write_all(b"0\n").await.unwrap();
+
+    tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        while stalling.flips.lock().unwrap().is_empty() {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+
+    // NOTE: Dropping waits for the workers, so the port is free afterwards.
+    tokio::task::spawn_blocking(move || drop(server))
+        .await
+        .unwrap();
+    serve_on(addr, stand_in.clone()).await;
+
+    drop(device);
+    forwarding.await.unwrap().unwrap();
+
+    assert_eq!(
+        *stand_in.flips.lock().unwrap(),
+        *stalling.flips.lock().unwrap()
+    );
+}
+
+#[tokio::test]
+async fn tick_triggers_are_stamped_with_device_time() {
+    let stand_in = StandIn::default();
+    let signaler = signaler(serve(stand_in.clone()).await);
+
+    let (device, host) = tokio::io::duplex(64);
+    let config = ClockConfig {
+        ticks_per_ms: 1000.0,
+        sync_interval_ms: 60_000,
+        tick_bits: None,
+        reanchor_interval_ms: 300_000,
+    };
+    let forwarding = tokio::spawn(async move {
+        let result = forward_synced_triggers(
+            host,
+            &mut Handler::from(LineParser::Default),
+            &config,
+            &signaler,
+        )
+        .await;
+        signaler.close().await;
+        result
+    });
+
+    let (reader, mut writer) = tokio::io::split(device);
+    let mut requests = BufReader::new(reader).lines();
+
+    assert_eq!(requests.next_line().await.unwrap().unwrap(), "SYNC");
+    writer.write_all(b"C 1000000\n").await.unwrap();
+
+    // NOTE: Delivered late as if the USB was busy, but the device time is what counts.
+    writer.write_all(b"T 2000000\n").await.unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    writer.write_all(b"T 32000000\n").await.unwrap();
+    drop(writer);
+    drop(requests);
+
+    forwarding.await.unwrap().unwrap();
+
+    let flips = stand_in.flips.lock().unwrap();
+    assert_eq!(flips.len(), 2);
+    assert_eq!(flips[1] - flips[0], 30_000_000);
+}
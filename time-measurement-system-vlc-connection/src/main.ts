@@ -5,19 +5,23 @@ import { ChannelCredentials } from "@grpc/grpc-js";
 import { ReadAllReply } from "./types/proto/running_observer.js";
 
 export default async function main() {
+  const password = process.env.VLC_PASSWORD;
+  if (password === undefined) {
+    throw new Error("VLC_PASSWORD is not set");
+  }
+
   const client = new RunningObserverClient(
     new GrpcTransport({
-      host: "[::1]:11000", // TODO: Config
+      host: process.env.RUNNING_OBSERVER_ADDR ?? "[::1]:11000",
       channelCredentials: ChannelCredentials.createInsecure(),
     })
   );
 
-  // TODO: Config
   const vlc = new Vlc.Client({
-    ip: "localhost",
-    port: 11002,
-    username: "timing-system-vlc-controller",
-    password: "1234"
+    ip: process.env.VLC_HOST ?? "localhost",
+    port: Number(process.env.VLC_PORT ?? 11002),
+    username: process.env.VLC_USERNAME ?? "timing-system-vlc-controller",
+    password
   });
 
   setInterval(async () => {
@@ -1,8 +1,18 @@
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tonic_build::compile_protos("../proto/records.proto").unwrap();
-    tonic_build::compile_protos("../proto/pending_car_queue.proto").unwrap();
-    tonic_build::compile_protos("../proto/running_observer.proto").unwrap();
-    tonic_build::compile_protos("../proto/aggrigated_change_broadcaster.proto").unwrap();
+    let out_dir = std::path::PathBuf::from(std::env::var("OUT_DIR")?);
+
+    tonic_build::configure()
+        .file_descriptor_set_path(out_dir.join("time_measurement_system_descriptor.bin"))
+        .compile(
+            &[
+                "../proto/records.proto",
+                "../proto/pending_car_queue.proto",
+                "../proto/running_observer.proto",
+                "../proto/aggrigated_change_broadcaster.proto",
+            ],
+            &["../proto"],
+        )
+        .unwrap();
 
     Ok(())
 }
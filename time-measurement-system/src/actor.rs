@@ -0,0 +1,229 @@
+use anyhow::Result;
+use log::trace;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{
+    config::Config, course::CourseState, event_bus::EventBus, pending_car_queue::PendingCarQueue,
+    prelude::*, records::Records, running_observer::RunningObserver, source::Source,
+};
+
+const COMMAND_BUFFER: usize = 64;
+
+/// State owned by the task spawned in `CoreHandle::spawn`. Commands are applied one at a time,
+/// so e.g. a stop and a queue edit never interleave.
+pub struct Core {
+    pub running_observer: RunningObserver,
+    pub pending_car_queue: PendingCarQueue,
+    pub records: Records,
+    event_bus: EventBus,
+}
+
+impl Core {
+    pub fn new(config: &Config, event_bus: EventBus) -> Core {
+        Core {
+            running_observer: RunningObserver::new(config, event_bus.clone()),
+            pending_car_queue: PendingCarQueue::new(config, event_bus.clone()),
+            records: Records::new(config, event_bus.clone()),
+            event_bus,
+        }
+    }
+
+    /// Starts `pending_car_id` if given, otherwise the head of the queue.
+    pub fn start(
+        &mut self,
+        timestamp: TimeStamp,
+        pending_car_id: &Option<String>,
+        source: Source,
+    ) -> Result<()> {
+        match pending_car_id {
+            Some(pending_car_id) => self.running_observer.start_car(
+                timestamp,
+                pending_car_id,
+                source,
+                &mut self.pending_car_queue,
+            ),
+            None => self
+                .running_observer
+                .start(timestamp, source, &mut self.pending_car_queue),
+        }
+    }
+
+    pub fn stop(
+        &mut self,
+        timestamp: TimeStamp,
+        car_id: &Option<RunningCarId>,
+        source: Source,
+    ) -> Result<()> {
+        self.running_observer
+            .stop(timestamp, car_id, source, &mut self.records)
+    }
+
+    pub fn cancel(
+        &mut self,
+        timestamp: TimeStamp,
+        car_id: &Option<RunningCarId>,
+        requeue: bool,
+    ) -> Result<()> {
+        self.running_observer
+            .cancel(timestamp, car_id, requeue, &mut self.pending_car_queue)
+    }
+
+    pub fn set_course_state(
+        &mut self,
+        timestamp: TimeStamp,
+        state: CourseState,
+        requeue: bool,
+    ) -> Result<()> {
+        self.running_observer.set_course_state(
+            timestamp,
+            state,
+            requeue,
+            &mut self.pending_car_queue,
+        )
+    }
+
+    pub fn expire(&mut self, now: TimeStamp) -> Result<()> {
+        self.running_observer.expire(now, &mut self.records)
+    }
+
+    pub fn flip_start_or_stop(&mut self, timestamp: TimeStamp, source: Source) -> Result<()> {
+        self.running_observer.flip_start_or_stop(
+            timestamp,
+            source,
+            &mut self.pending_car_queue,
+            &mut self.records,
+        )
+    }
+}
+
+type Command = Box<dyn FnOnce(&mut Core) + Send>;
+
+/// The core task has stopped, e.g. by a panicking command, so no command can be applied.
+#[derive(Debug, thiserror::Error)]
+#[error("Core task was stopped")]
+pub struct CoreStopped;
+
+#[derive(Clone)]
+pub struct CoreHandle {
+    sender: mpsc::Sender<Command>,
+    event_bus: EventBus,
+}
+
+impl CoreHandle {
+    pub fn spawn(mut core: Core) -> CoreHandle {
+        let (sender, mut receiver) = mpsc::channel::<Command>(COMMAND_BUFFER);
+        let event_bus = core.event_bus.clone();
+
+        tokio::spawn(async move {
+            while let Some(command) = receiver.recv().await {
+                command(&mut core);
+            }
+            trace!("Core stopped");
+        });
+
+        CoreHandle { sender, event_bus }
+    }
+
+    /// Applies `command` on the core task and returns its result.
+    pub async fn run<F, R>(&self, command: F) -> Result<R, CoreStopped>
+    where
+        F: FnOnce(&mut Core) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+
+        self.sender
+            .send(Box::new(move |core| {
+                let _ = tx.send(command(core));
+            }))
+            .await
+            .map_err(|_| CoreStopped)?;
+
+        rx.await.map_err(|_| CoreStopped)
+    }
+
+    /// Completes once the core task has stopped, e.g. by a panicking command.
+    pub async fn stopped(&self) {
+        self.sender.closed().await
+    }
+
+    pub fn event_bus(&self) -> &EventBus {
+        &self.event_bus
+    }
+}
+
+pub mod server {
+    use tonic::Status;
+
+    use super::CoreStopped;
+
+    impl From<CoreStopped> for Status {
+        fn from(stopped: CoreStopped) -> Self {
+            Status::unavailable(stopped.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Core, CoreHandle};
+    use crate::{config::Config, event_bus::EventBus, source::Source};
+
+    #[tokio::test]
+    async fn stop_records_and_start_consumes_queue() {
+        let config = Config::for_test();
+
+        let core = CoreHandle::spawn(Core::new(&config, EventBus::new()));
+
+        core.run(|core| core.pending_car_queue.insert(r#""0""#.to_string(), None))
+            .await
+            .unwrap()
+            .unwrap();
+
+        core.run(|core| core.flip_start_or_stop(0, Source::Sensor("gate".to_string())))
+            .await
+            .unwrap()
+            .unwrap();
+        core.run(|core| core.flip_start_or_stop(10, Source::HandTimed))
+            .await
+            .unwrap()
+            .unwrap();
+
+        let (queue, running_cars, records) = core
+            .run(|core| {
+                (
+                    core.pending_car_queue.queue().len(),
+                    core.running_observer.running_car().len(),
+                    core.records.records().clone(),
+                )
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(queue, 1);
+        assert_eq!(running_cars, 0);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].meta, r#""default_metadata""#);
+        assert_eq!(records[0].duration, 10);
+        assert_eq!(records[0].start_source, Source::Sensor("gate".to_string()));
+        assert_eq!(records[0].stop_source, Source::HandTimed);
+        assert!(records[0].is_hand_timed());
+    }
+
+    #[tokio::test]
+    async fn stopped_after_panicking_command() {
+        let config = Config::for_test();
+
+        let core = CoreHandle::spawn(Core::new(&config, EventBus::new()));
+
+        core.run(|_| panic!("Broken command")).await.unwrap_err();
+
+        tokio::time::timeout(std::time::Duration::from_secs(1), core.stopped())
+            .await
+            .unwrap();
+
+        core.run(|core| core.running_observer.running_car().len())
+            .await
+            .unwrap_err();
+    }
+}
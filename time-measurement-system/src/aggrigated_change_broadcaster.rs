@@ -1,37 +1,86 @@
-use log::error;
+use log::{error, trace};
+use nanoid::nanoid;
 use std::sync::Arc;
-use tokio::{select, sync::Mutex};
+use tokio::sync::broadcast::error::TryRecvError;
 
 use crate::{
-    pending_car_queue::PendingCarQueue, records::Records, running_observer::RunningObserver,
+    actor::{Core, CoreHandle, CoreStopped},
+    course::CourseState,
+    event_bus::changed,
+    pending_car_queue::PendingCar,
+    records::Record,
+    running_observer::RunningCar,
 };
 
+/// State of `RunningObserver`, `PendingCarQueue` and `Records` read at the same point in time.
+#[derive(Clone, Debug, Default)]
+pub struct Snapshot {
+    /// Identifies the broadcaster, since revisions restart from 0 whenever the server restarts.
+    pub epoch: String,
+    pub revision: u64,
+    pub pending_cars: Vec<PendingCar>,
+    pub running_cars: Vec<RunningCar>,
+    pub records: Vec<Record>,
+    pub course_state: CourseState,
+}
+
 pub struct AggrigatedChangeBroadcaster {
-    watcher: tokio::sync::watch::Receiver<()>,
+    watcher: tokio::sync::watch::Receiver<Arc<Snapshot>>,
 }
 
 impl AggrigatedChangeBroadcaster {
-    pub async fn new(
-        running_observer: Arc<Mutex<RunningObserver>>,
-        pending_car_queue: Arc<Mutex<PendingCarQueue>>,
-        records: Arc<Mutex<Records>>,
-    ) -> AggrigatedChangeBroadcaster {
-        let mut running_observer_watcher = running_observer.lock().await.watcher().clone();
-        let mut pending_car_queue_watcher = pending_car_queue.lock().await.watcher().clone();
-        let mut records_watcher = records.lock().await.watcher().clone();
+    pub async fn new(core: CoreHandle) -> Result<AggrigatedChangeBroadcaster, CoreStopped> {
+        let mut events = core.event_bus().subscribe();
+
+        let epoch = nanoid!();
+        let mut revision = 0;
 
-        let (on_change, watcher) = tokio::sync::watch::channel(());
+        let first_epoch = epoch.clone();
+        let (on_change, watcher) = tokio::sync::watch::channel(Arc::new(
+            core.run(move |core| Self::snapshot(first_epoch, revision, core))
+                .await?,
+        ));
 
         tokio::spawn(async move {
-            while select! { w = running_observer_watcher.changed() => w.is_ok(), w = pending_car_queue_watcher.changed() => w.is_ok(), w = records_watcher.changed() => w.is_ok()}
-            {
+            while changed(&mut events, |_| true).await {
+                // NOTE: A single operation publishes several events (e.g. stopping a car adds a
+                // record and consumes the queue), so take them at once.
+                while !matches!(
+                    events.try_recv(),
+                    Err(TryRecvError::Empty | TryRecvError::Closed)
+                ) {}
+
+                revision += 1;
+
+                // NOTE: Watchers see the channel closed once the core has stopped.
+                let epoch = epoch.clone();
+                let Ok(snapshot) = core
+                    .run(move |core| Self::snapshot(epoch, revision, core))
+                    .await
+                else {
+                    break;
+                };
+
+                trace!("Broadcasting snapshot revision {}", revision);
+
                 on_change
-                    .send(())
+                    .send(Arc::new(snapshot))
                     .unwrap_or_else(|_| error!("Failed to broadcast change"));
             }
         });
 
-        AggrigatedChangeBroadcaster { watcher }
+        Ok(AggrigatedChangeBroadcaster { watcher })
+    }
+
+    fn snapshot(epoch: String, revision: u64, core: &Core) -> Snapshot {
+        Snapshot {
+            epoch,
+            revision,
+            pending_cars: core.pending_car_queue.queue().clone(),
+            running_cars: core.running_observer.running_car().clone(),
+            records: core.records.records().clone(),
+            course_state: core.running_observer.course_state(),
+        }
     }
 }
 
@@ -46,7 +95,22 @@ pub mod server {
 
     use crate::proto::aggrigated_change_broadcaster::{self as proto, SubscribeChangeReply};
 
-    use super::AggrigatedChangeBroadcaster;
+    use super::{AggrigatedChangeBroadcaster, Snapshot};
+
+    impl From<&Snapshot> for SubscribeChangeReply {
+        fn from(snapshot: &Snapshot) -> Self {
+            SubscribeChangeReply {
+                revision: snapshot.revision,
+                pending_cars: snapshot.pending_cars.iter().map(Into::into).collect(),
+                running_cars: snapshot.running_cars.iter().map(Into::into).collect(),
+                records: snapshot.records.iter().map(Into::into).collect(),
+                course_state: crate::proto::running_observer::CourseState::from(
+                    snapshot.course_state,
+                ) as i32,
+                epoch: snapshot.epoch.clone(),
+            }
+        }
+    }
 
     #[async_trait]
     impl proto::aggrigated_change_broadcaster_server::AggrigatedChangeBroadcaster
@@ -62,11 +126,11 @@ pub mod server {
             let (tx, rx) = tokio::sync::mpsc::channel(1);
             let mut watcher = self.lock().await.watcher.clone();
             tokio::spawn(async move {
-                while watcher.changed().await.is_ok() {
-                    trace!("aggrigated change received!");
+                loop {
+                    let snapshot = watcher.borrow_and_update().clone();
 
                     match tx
-                        .send(Result::<_, Status>::Ok(SubscribeChangeReply {}))
+                        .send(Result::<_, Status>::Ok(snapshot.as_ref().into()))
                         .await
                     {
                         Ok(_) => {}
@@ -74,6 +138,11 @@ pub mod server {
                             break;
                         }
                     }
+
+                    if watcher.changed().await.is_err() {
+                        break;
+                    }
+                    trace!("aggrigated change received!");
                 }
             });
 
@@ -85,3 +154,55 @@ pub mod server {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        actor::{Core, CoreHandle},
+        config::Config,
+        event_bus::EventBus,
+        source::Source,
+    };
+
+    use super::AggrigatedChangeBroadcaster;
+
+    #[tokio::test]
+    async fn broadcasts_consistent_snapshot() {
+        let config = Config::for_test();
+
+        let core = CoreHandle::spawn(Core::new(&config, EventBus::new()));
+
+        let broadcaster = AggrigatedChangeBroadcaster::new(core.clone())
+            .await
+            .unwrap();
+
+        let mut watcher = broadcaster.watcher.clone();
+
+        core.run(|core| core.start(0, &None, Source::Unspecified))
+            .await
+            .unwrap()
+            .unwrap();
+        core.run(|core| core.stop(10, &None, Source::Unspecified))
+            .await
+            .unwrap()
+            .unwrap();
+
+        let epoch = watcher.borrow().epoch.clone();
+        let mut last_revision = 0;
+
+        loop {
+            watcher.changed().await.unwrap();
+            let snapshot = watcher.borrow_and_update().clone();
+
+            assert_eq!(snapshot.epoch, epoch);
+            assert!(snapshot.revision > last_revision);
+            last_revision = snapshot.revision;
+
+            if snapshot.records.len() == 1 {
+                assert_eq!(snapshot.running_cars.len(), 0);
+                assert_eq!(snapshot.pending_cars.len(), 1);
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,277 @@
+use std::{collections::VecDeque, sync::Arc};
+
+use log::trace;
+use nanoid::nanoid;
+use tokio::sync::broadcast;
+
+/// Number of revisions kept for subscribers resuming after reconnect.
+pub const HISTORY_CAPACITY: usize = 1024;
+
+#[derive(Clone, Debug)]
+pub enum Change<T> {
+    Inserted { position: usize, item: T },
+    Updated(T),
+    Removed(String),
+}
+
+/// Changes made by a single operation.
+#[derive(Clone, Debug)]
+pub struct Delta<T> {
+    pub revision: u64,
+    pub changes: Vec<Change<T>>,
+}
+
+#[derive(Clone, Debug)]
+pub enum Message<T> {
+    /// Whole state. Subscribers should discard what they have received so far.
+    Reset {
+        revision: u64,
+        items: Vec<T>,
+    },
+    Delta(Arc<Delta<T>>),
+}
+
+/// Everything a subscriber needs to follow a `ChangeLog`, see `ChangeLog::resume`.
+pub struct Resumption<T> {
+    pub epoch: String,
+    /// Brings the subscriber up to date.
+    pub messages: Vec<Message<T>>,
+    /// Deltas after `messages`.
+    pub receiver: broadcast::Receiver<Arc<Delta<T>>>,
+}
+
+pub struct ChangeLog<T> {
+    /// Identifies this log, since revisions restart from 0 whenever the server restarts.
+    epoch: String,
+    revision: u64,
+    history: VecDeque<Arc<Delta<T>>>,
+    sender: broadcast::Sender<Arc<Delta<T>>>,
+}
+
+impl<T: Clone> Default for ChangeLog<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone> ChangeLog<T> {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(HISTORY_CAPACITY);
+
+        ChangeLog {
+            epoch: nanoid!(),
+            revision: 0,
+            history: VecDeque::new(),
+            sender,
+        }
+    }
+
+    /// Returns the pushed delta, or `None` if there were no changes.
+    pub fn push(&mut self, changes: Vec<Change<T>>) -> Option<Arc<Delta<T>>> {
+        if changes.is_empty() {
+            return None;
+        }
+
+        self.revision += 1;
+
+        let delta = Arc::new(Delta {
+            revision: self.revision,
+            changes,
+        });
+
+        if self.history.len() >= HISTORY_CAPACITY {
+            self.history.pop_front();
+        }
+        self.history.push_back(delta.clone());
+
+        // NOTE: Fails only when nobody subscribes.
+        let _ = self.sender.send(delta.clone());
+
+        Some(delta)
+    }
+
+    /// Deltas after `revision`, or `None` if they are no longer kept.
+    pub fn since(&self, revision: u64) -> Option<Vec<Arc<Delta<T>>>> {
+        if revision > self.revision {
+            return None;
+        }
+
+        if revision == self.revision {
+            return Some(Vec::new());
+        }
+
+        match self.history.front() {
+            Some(oldest) if oldest.revision <= revision + 1 => Some(
+                self.history
+                    .iter()
+                    .filter(|delta| delta.revision > revision)
+                    .cloned()
+                    .collect(),
+            ),
+            _ => None,
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<Delta<T>>> {
+        self.sender.subscribe()
+    }
+
+    /// Messages to bring a subscriber at `since` of `epoch` up to date followed by a receiver for
+    /// the later ones. A subscriber of another epoch is reset. Must be called inside `CoreHandle::run`
+    /// so that no change is pushed in between and missed.
+    pub fn resume(&self, epoch: &str, since: Option<u64>, items: &[T]) -> Resumption<T> {
+        let since = since.filter(|_| epoch == self.epoch);
+
+        let messages = match since.and_then(|since| self.since(since)) {
+            Some(deltas) => {
+                trace!("Resuming {} deltas since {:?}", deltas.len(), since);
+                deltas.into_iter().map(Message::Delta).collect()
+            }
+            None => vec![Message::Reset {
+                revision: self.revision,
+                items: items.to_vec(),
+            }],
+        };
+
+        Resumption {
+            epoch: self.epoch.clone(),
+            messages,
+            receiver: self.subscribe(),
+        }
+    }
+}
+
+pub mod server {
+    use std::pin::Pin;
+
+    use log::trace;
+    use tokio::sync::broadcast::error::RecvError;
+    use tokio_stream::Stream;
+    use tonic::Status;
+
+    use super::{Message, Resumption};
+
+    pub type DeltaStream<R> = Pin<Box<dyn Stream<Item = Result<R, Status>> + Send>>;
+
+    /// Sends `messages` and then everything from `receiver`, each with the epoch. Ends with
+    /// DATA_LOSS when the subscriber falls too far behind; it can resume with the last revision it
+    /// received.
+    pub fn delta_stream<T, R>(
+        resumption: Resumption<T>,
+        to_reply: fn(&str, &Message<T>) -> R,
+    ) -> DeltaStream<R>
+    where
+        T: Clone + Send + Sync + 'static,
+        R: Send + 'static,
+    {
+        let Resumption {
+            epoch,
+            messages,
+            mut receiver,
+        } = resumption;
+        let (tx, rx) = tokio::sync::mpsc::channel(1);
+
+        tokio::spawn(async move {
+            for message in messages {
+                if tx.send(Ok(to_reply(&epoch, &message))).await.is_err() {
+                    return;
+                }
+            }
+
+            loop {
+                let reply = match receiver.recv().await {
+                    Ok(delta) => Ok(to_reply(&epoch, &Message::Delta(delta))),
+                    Err(RecvError::Lagged(skipped)) => {
+                        trace!("Delta subscriber lagged by {}", skipped);
+                        Err(Status::data_loss(
+                            "Subscriber lagged. Resume from the last revision.",
+                        ))
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+
+                let is_err = reply.is_err();
+
+                if tx.send(reply).await.is_err() || is_err {
+                    break;
+                }
+            }
+        });
+
+        Box::pin(tokio_stream::wrappers::ReceiverStream::new(rx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Change, ChangeLog, Message, HISTORY_CAPACITY};
+
+    #[test]
+    fn resumes_from_revision() {
+        let mut log = ChangeLog::<String>::new();
+
+        log.push(vec![Change::Inserted {
+            position: 0,
+            item: "a".to_string(),
+        }]);
+        log.push(vec![Change::Removed("a".to_string())]);
+        log.push(vec![]);
+
+        assert_eq!(log.revision, 2);
+        assert_eq!(log.since(0).unwrap().len(), 2);
+        assert_eq!(log.since(1).unwrap()[0].revision, 2);
+        assert_eq!(log.since(2).unwrap().len(), 0);
+        assert!(log.since(3).is_none());
+    }
+
+    #[test]
+    fn resets_when_history_is_gone() {
+        let mut log = ChangeLog::<String>::new();
+
+        for _ in 0..HISTORY_CAPACITY + 1 {
+            log.push(vec![Change::Removed("a".to_string())]);
+        }
+
+        assert!(log.since(0).is_none());
+        assert!(log.since(1).is_some());
+
+        let resumption = log.resume(&log.epoch, Some(0), &["a".to_string()]);
+
+        assert!(matches!(
+            &resumption.messages[..],
+            [Message::Reset { revision, items }] if *revision == HISTORY_CAPACITY as u64 + 1 && items.len() == 1
+        ));
+    }
+
+    #[tokio::test]
+    async fn broadcasts_pushed_delta() {
+        let mut log = ChangeLog::<String>::new();
+        let mut resumption = log.resume("", None, &[]);
+
+        assert!(matches!(
+            &resumption.messages[..],
+            [Message::Reset { revision: 0, .. }]
+        ));
+        assert_eq!(resumption.epoch, log.epoch);
+
+        log.push(vec![Change::Updated("a".to_string())]);
+
+        assert_eq!(resumption.receiver.recv().await.unwrap().revision, 1);
+    }
+
+    #[test]
+    fn resets_subscriber_of_another_epoch() {
+        let mut log = ChangeLog::<String>::new();
+        log.push(vec![Change::Updated("a".to_string())]);
+
+        // NOTE: As if the client saw revision 0 before the server restarted.
+        let resumption = log.resume("previous", Some(0), &["a".to_string()]);
+        assert!(matches!(
+            &resumption.messages[..],
+            [Message::Reset { revision: 1, .. }]
+        ));
+
+        let resumption = log.resume(&log.epoch, Some(0), &["a".to_string()]);
+        assert!(matches!(&resumption.messages[..], [Message::Delta(_)]));
+    }
+}
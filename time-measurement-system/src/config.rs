@@ -16,8 +16,94 @@ pub struct Server {
   pub addr: String
 }
 
+#[derive(Deserialize, Default, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CourseMode {
+  /// One car on course. `FlipRunningState` starts it and stops it.
+  #[default]
+  Flip,
+  /// Several cars on course, started and stopped explicitly.
+  Multi
+}
+
+#[derive(Deserialize, Default, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeoutAction {
+  /// Stops the car and records it with metadata `status` set to `DNF`. The metadata must be an
+  /// object accepting it.
+  #[default]
+  Dnf,
+  /// Keeps the car running but marks it as timed out. The car no longer counts as on course, so
+  /// the next car can start and stops without an id skip it.
+  Flag
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct RunTimeout {
+  pub max_run_time_ms: u64,
+  #[serde(default)]
+  pub action: TimeoutAction
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct Course {
+  pub mode: CourseMode,
+  /// Only for `CourseMode::Multi`. Unlimited if not set.
+  pub max_cars_on_course: Option<usize>,
+  /// Cars run forever if not set.
+  pub timeout: Option<RunTimeout>
+}
+
+#[derive(Deserialize, Default, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Rounding {
+  /// Drops the digits below the precision, as most timing rules do.
+  #[default]
+  Truncate,
+  HalfUp,
+  Up
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(default)]
+pub struct Display {
+  /// Decimal places of seconds, up to 6.
+  pub precision: u32,
+  pub rounding: Rounding
+}
+
+impl Default for Display {
+  fn default() -> Self {
+    Display {
+      precision: 3,
+      rounding: Rounding::default()
+    }
+  }
+}
+
 #[derive(Deserialize, Default)]
 pub struct Config {
   pub record: Record,
-  pub server: Server
+  pub server: Server,
+  #[serde(default)]
+  pub course: Course,
+  #[serde(default)]
+  pub display: Display
+}
+
+#[cfg(test)]
+impl Config {
+  /// String metadata defaulting to `"default_metadata"`, which most tests need.
+  pub fn for_test() -> Self {
+    Config {
+      record: Record {
+        metadata: RecordMetadata {
+          schema: serde_json::from_str(r#"{"type": "string"}"#).unwrap(),
+          default: serde_json::from_str(r#""default_metadata""#).unwrap()
+        }
+      },
+      ..Config::default()
+    }
+  }
 }
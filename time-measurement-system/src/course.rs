@@ -0,0 +1,86 @@
+use anyhow::{bail, Result};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum CourseState {
+    /// Cars may start.
+    #[default]
+    Green,
+    /// The course is held. Times of running cars are invalid.
+    RedFlag,
+    Closed,
+}
+
+impl CourseState {
+    pub fn transition(self, to: CourseState) -> Result<CourseState> {
+        use CourseState::*;
+
+        match (self, to) {
+            (Green, RedFlag)
+            | (Green, Closed)
+            | (RedFlag, Green)
+            | (RedFlag, Closed)
+            | (Closed, Green) => Ok(to),
+            _ => bail!("Course can not be changed from {:?} to {:?}", self, to),
+        }
+    }
+
+    pub fn allows_start(self) -> bool {
+        self == CourseState::Green
+    }
+}
+
+pub mod server {
+    use tonic::Status;
+
+    use super::CourseState;
+    use crate::proto::running_observer as proto;
+
+    impl From<CourseState> for proto::CourseState {
+        fn from(state: CourseState) -> Self {
+            match state {
+                CourseState::Green => proto::CourseState::Green,
+                CourseState::RedFlag => proto::CourseState::RedFlag,
+                CourseState::Closed => proto::CourseState::Closed,
+            }
+        }
+    }
+
+    impl TryFrom<i32> for CourseState {
+        type Error = Status;
+
+        fn try_from(state: i32) -> Result<Self, Self::Error> {
+            match proto::CourseState::from_i32(state) {
+                Some(proto::CourseState::Green) => Ok(CourseState::Green),
+                Some(proto::CourseState::RedFlag) => Ok(CourseState::RedFlag),
+                Some(proto::CourseState::Closed) => Ok(CourseState::Closed),
+                None => Err(Status::invalid_argument(format!(
+                    "Unknown course state {}",
+                    state
+                ))),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CourseState;
+
+    #[test]
+    fn transitions() {
+        CourseState::Green
+            .transition(CourseState::RedFlag)
+            .unwrap()
+            .transition(CourseState::Closed)
+            .unwrap()
+            .transition(CourseState::Green)
+            .unwrap();
+
+        CourseState::Closed
+            .transition(CourseState::RedFlag)
+            .unwrap_err();
+        CourseState::Green
+            .transition(CourseState::Green)
+            .unwrap_err();
+    }
+}
@@ -0,0 +1,95 @@
+use log::trace;
+use tokio::sync::broadcast;
+
+pub const EVENT_BUS_CAPACITY: usize = 1024;
+
+// NOTE: Events only tell what happened. Subscribers read the state through `CoreHandle`, or the
+// deltas of each `ChangeLog`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    CarStarted,
+    CarStopped,
+    CarCancelled,
+    RunningCarUpdated,
+    CourseStateChanged,
+    RecordAdded,
+    RecordUpdated,
+    RecordRemoved,
+    RecordsCleared,
+    /// Published once per operation on the queue.
+    QueueChanged,
+}
+
+impl Event {
+    pub fn is_running_observer_event(&self) -> bool {
+        matches!(
+            self,
+            Event::CarStarted
+                | Event::CarStopped
+                | Event::CarCancelled
+                | Event::RunningCarUpdated
+                | Event::CourseStateChanged
+        )
+    }
+
+    pub fn is_records_event(&self) -> bool {
+        matches!(
+            self,
+            Event::RecordAdded
+                | Event::RecordUpdated
+                | Event::RecordRemoved
+                | Event::RecordsCleared
+        )
+    }
+
+    pub fn is_pending_car_queue_event(&self) -> bool {
+        matches!(self, Event::QueueChanged)
+    }
+}
+
+/// Domain events published by `RunningObserver`, `PendingCarQueue` and `Records`.
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<Event>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BUS_CAPACITY);
+
+        EventBus { sender }
+    }
+
+    pub fn publish(&self, event: Event) {
+        trace!("Publishing {:?}", event);
+
+        // NOTE: Fails only when nobody subscribes.
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.sender.subscribe()
+    }
+}
+
+/// Waits for an event matching `filter`. Returns `false` once the bus is closed. Lagging counts
+/// as a change since the missed events may have matched.
+pub async fn changed(
+    receiver: &mut broadcast::Receiver<Event>,
+    filter: fn(&Event) -> bool,
+) -> bool {
+    loop {
+        match receiver.recv().await {
+            Ok(event) if filter(&event) => return true,
+            Ok(_) => {}
+            Err(broadcast::error::RecvError::Lagged(_)) => return true,
+            Err(broadcast::error::RecvError::Closed) => return false,
+        }
+    }
+}
@@ -1,24 +1,41 @@
 use std::sync::Arc;
 
-use clap::{arg, command, Parser};
+use clap::Parser;
+use log::warn;
 use tokio::{fs::read_to_string, sync::Mutex};
-use tonic_web;
+use tonic::{server::NamedService, transport::server::TcpIncoming};
+use tonic_health::{server::HealthReporter, ServingStatus};
 
 use crate::config::Config;
+use crate::proto::{
+    aggrigated_change_broadcaster::aggrigated_change_broadcaster_server::AggrigatedChangeBroadcasterServer,
+    pending_car_queue::pending_car_queue_server::PendingCarQueueServer,
+    records::records_server::RecordsServer,
+    running_observer::running_observer_server::RunningObserverServer,
+};
 
 mod prelude {
+    /// Microseconds since the unix epoch.
     pub type TimeStamp = i64;
+    /// Microseconds.
     pub type Duration = i64;
     pub type MetaData = String;
     pub type RunningCarId = String;
 }
 
+mod actor;
 mod aggrigated_change_broadcaster;
+mod change_log;
 mod config;
+mod course;
+mod event_bus;
 mod pending_car_queue;
 mod proto;
 mod records;
 mod running_observer;
+mod source;
+mod timeout;
+mod units;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -27,12 +44,32 @@ struct Args {
     config: String,
 }
 
+/// The gRPC services reported by the health service besides the server as a whole ("").
+const SERVICE_NAMES: [&str; 4] = [
+    <RunningObserverServer<actor::CoreHandle> as NamedService>::NAME,
+    <PendingCarQueueServer<actor::CoreHandle> as NamedService>::NAME,
+    <RecordsServer<actor::CoreHandle> as NamedService>::NAME,
+    <AggrigatedChangeBroadcasterServer<
+        Arc<Mutex<aggrigated_change_broadcaster::AggrigatedChangeBroadcaster>>,
+    > as NamedService>::NAME,
+];
+
+async fn set_serving_status(health_reporter: &mut HealthReporter, status: ServingStatus) {
+    for name in std::iter::once("").chain(SERVICE_NAMES) {
+        health_reporter.set_service_status(name, status).await;
+    }
+}
+
 #[tokio::main]
 async fn main() {
     env_logger::init();
 
     let args = Args::parse();
 
+    let (mut health_reporter, health) = tonic_health::server::health_reporter();
+
+    set_serving_status(&mut health_reporter, ServingStatus::NotServing).await;
+
     let config_string = read_to_string(args.config)
         .await
         .unwrap_or_else(|error| panic!("Failed to load config! {:?}", error));
@@ -40,27 +77,45 @@ async fn main() {
     let config = serde_json::from_str::<Config>(&config_string)
         .unwrap_or_else(|error| panic!("Invalid config data! {:?}", error));
 
-    let pending_car_queue = Arc::new(Mutex::new(pending_car_queue::PendingCarQueue::new(&config)));
-    let records = Arc::new(Mutex::new(records::Records::new(&config)));
-    let observer = Arc::new(Mutex::new(running_observer::RunningObserver::new(
-        &config,
-        pending_car_queue.clone(),
-        records.clone(),
-    )));
+    let core = actor::CoreHandle::spawn(actor::Core::new(&config, event_bus::EventBus::new()));
+
+    if config.course.timeout.is_some() {
+        timeout::spawn(core.clone());
+    }
 
     let aggrigated_change_broadcaster = Arc::new(Mutex::new(
-        aggrigated_change_broadcaster::AggrigatedChangeBroadcaster::new(
-            observer.clone(),
-            pending_car_queue.clone(),
-            records.clone(),
-        ).await,
+        aggrigated_change_broadcaster::AggrigatedChangeBroadcaster::new(core.clone())
+            .await
+            .unwrap_or_else(|e| panic!("Failed to take the first snapshot! {:?}", e)),
     ));
 
+    let reflection = tonic_reflection::server::Builder::configure()
+        .register_encoded_file_descriptor_set(proto::FILE_DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(tonic_health::pb::FILE_DESCRIPTOR_SET)
+        .build()
+        .unwrap_or_else(|e| panic!("Failed to build reflection service! {:?}", e));
+
+    let incoming = TcpIncoming::new(config.server.addr.parse().unwrap(), false, None)
+        .unwrap_or_else(|e| panic!("Failed to bind {}! {:?}", config.server.addr, e));
+
+    // NOTE: Everything above panics on failure and the address is bound, so reaching here means
+    // the server is ready.
+    set_serving_status(&mut health_reporter, ServingStatus::Serving).await;
+
+    let stopped = core.clone();
+    tokio::spawn(async move {
+        stopped.stopped().await;
+        warn!("Core task stopped, no longer serving");
+        set_serving_status(&mut health_reporter, ServingStatus::NotServing).await;
+    });
+
     tonic::transport::Server::builder()
         .accept_http1(true)
-        .add_service(tonic_web::enable(proto::running_observer::running_observer_server::RunningObserverServer::new(observer)))
-        .add_service(tonic_web::enable(proto::pending_car_queue::pending_car_queue_server::PendingCarQueueServer::new(pending_car_queue)))
-        .add_service(tonic_web::enable(proto::records::records_server::RecordsServer::new(records)))
-        .add_service(tonic_web::enable(proto::aggrigated_change_broadcaster::aggrigated_change_broadcaster_server::AggrigatedChangeBroadcasterServer::new(aggrigated_change_broadcaster)))
-        .serve(config.server.addr.parse().unwrap()).await.unwrap();
+        .add_service(tonic_web::enable(health))
+        .add_service(reflection)
+        .add_service(tonic_web::enable(RunningObserverServer::new(core.clone())))
+        .add_service(tonic_web::enable(PendingCarQueueServer::new(core.clone())))
+        .add_service(tonic_web::enable(RecordsServer::new(core)))
+        .add_service(tonic_web::enable(AggrigatedChangeBroadcasterServer::new(aggrigated_change_broadcaster)))
+        .serve_with_incoming(incoming).await.unwrap();
 }
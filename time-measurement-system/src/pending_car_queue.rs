@@ -1,33 +1,39 @@
 use anyhow::{anyhow, bail, Result};
-use async_trait::async_trait;
 use jsonschema::{JSONSchema, ValidationError};
 use log::{error, trace};
 use nanoid::nanoid;
 
-use crate::{config::Config, prelude::*};
+use crate::{
+    change_log::{Change, ChangeLog},
+    config::Config,
+    event_bus::{Event, EventBus},
+    prelude::*,
+};
 
 // TODO: validate metadata
 #[derive(Clone, Debug)]
 pub struct PendingCar {
     id: String,
     meta: MetaData,
+    /// Put back by a cancelled run.
+    rerun: bool,
 }
 
 pub struct PendingCarQueue {
     queue: Vec<PendingCar>,
     meta_schema: JSONSchema,
     default_meta_data: String,
-    on_change: tokio::sync::watch::Sender<Vec<PendingCar>>,
-    watcher: tokio::sync::watch::Receiver<Vec<PendingCar>>,
+    event_bus: EventBus,
+    change_log: ChangeLog<PendingCar>,
 }
 
 impl PendingCarQueue {
-    pub fn new(config: &Config) -> Self {
+    pub fn new(config: &Config, event_bus: EventBus) -> Self {
         let queue = vec![PendingCar {
             id: nanoid!(),
             meta: config.record.metadata.default.to_string(),
+            rerun: false,
         }];
-        let (on_change, watcher) = tokio::sync::watch::channel(queue.clone());
         let meta_schema = JSONSchema::compile(&config.record.metadata.schema)
             .unwrap_or_else(|e| panic!("Invalid metadata schema! {:?}", e));
 
@@ -44,31 +50,38 @@ impl PendingCarQueue {
             queue,
             meta_schema,
             default_meta_data: config.record.metadata.default.to_string(),
-            on_change,
-            watcher,
+            event_bus,
+            change_log: ChangeLog::new(),
         }
     }
 
     pub fn insert(&mut self, meta: MetaData, index: Option<usize>) -> Result<()> {
         trace!("Inserting");
-        let car = PendingCar {
-            id: nanoid!(),
-            meta,
-        };
+        self.insert_car(
+            PendingCar {
+                id: nanoid!(),
+                meta,
+                rerun: false,
+            },
+            index,
+        )
+    }
 
+    fn insert_car(&mut self, car: PendingCar, index: Option<usize>) -> Result<()> {
         self.validate_record(&car)?;
 
-        if let Some(index) = index {
-            if index > self.queue.len() {
-                bail!("Index {} was too large", index);
-            }
+        let position = index.unwrap_or(self.queue.len());
 
-            self.queue.insert(index, car);
-        } else {
-            self.queue.push(car);
+        if position > self.queue.len() {
+            bail!("Index {} was too large", position);
         }
 
-        self.promote_change();
+        self.queue.insert(position, car.clone());
+
+        self.promote_change(vec![Change::Inserted {
+            position,
+            item: car,
+        }]);
 
         Ok(())
     }
@@ -78,15 +91,30 @@ impl PendingCarQueue {
         let index = self.find_car_index(id)?;
         self.queue.remove(index);
 
-        if self.queue.len() == 0 {
-            self.insert(self.default_meta_data.clone(), None)
-                .map_err(|e| anyhow!("Logic Error {}", e))?;
-        }
+        let mut changes = vec![Change::Removed(id.to_string())];
+        self.fill_default_car(&mut changes);
+        self.promote_change(changes);
 
-        self.promote_change();
         Ok(())
     }
 
+    /// Keeps at least one car in the queue, adding its insertion to `changes` so that subscribers
+    /// see a single change. The default metadata was validated on creation.
+    fn fill_default_car(&mut self, changes: &mut Vec<Change<PendingCar>>) {
+        if self.queue.is_empty() {
+            let car = PendingCar {
+                id: nanoid!(),
+                meta: self.default_meta_data.clone(),
+                rerun: false,
+            };
+            self.queue.push(car.clone());
+            changes.push(Change::Inserted {
+                position: 0,
+                item: car,
+            });
+        }
+    }
+
     pub fn update(&mut self, id: &str, meta: String) -> Result<()> {
         trace!("Updating metadata {:?} for id {:?}", meta, id);
         let index = self.find_car_index(id)?;
@@ -103,9 +131,9 @@ impl PendingCarQueue {
         .ok_or(anyhow!("Logic Error"))?
         .meta = meta;*/
 
-        *self.queue.get_mut(index).ok_or(anyhow!("Logic Error"))? = new_record;
+        *self.queue.get_mut(index).ok_or(anyhow!("Logic Error"))? = new_record.clone();
 
-        self.promote_change();
+        self.promote_change(vec![Change::Updated(new_record)]);
         Ok(())
     }
 
@@ -116,19 +144,25 @@ impl PendingCarQueue {
     ) -> Result<()> {
         trace!("Insert many");
 
-        let position = position.unwrap_or(self.queue.len());
-
-        if position > self.queue.len() {
-            bail!("Index {} was too large", position);
-        }
-
         let new_records = metas
             .map(|meta| PendingCar {
                 id: nanoid!(),
                 meta,
+                rerun: false,
             })
             .collect::<Vec<PendingCar>>();
 
+        self.insert_cars(new_records, position)
+    }
+
+    /// Inserts all of `new_records` as a single change, or none of them if any is invalid.
+    fn insert_cars(&mut self, new_records: Vec<PendingCar>, position: Option<usize>) -> Result<()> {
+        let position = position.unwrap_or(self.queue.len());
+
+        if position > self.queue.len() {
+            bail!("Index {} was too large", position);
+        }
+
         if !new_records
             .iter()
             .all(|record| self.validate_record(record).is_ok())
@@ -136,22 +170,31 @@ impl PendingCarQueue {
             bail!("Request includes invalid record!");
         }
 
-        self.queue.splice(&position..&position, new_records);
+        let changes = new_records
+            .iter()
+            .enumerate()
+            .map(|(offset, car)| Change::Inserted {
+                position: position + offset,
+                item: car.clone(),
+            })
+            .collect();
+
+        self.queue.splice(position..position, new_records);
 
-        self.promote_change();
+        self.promote_change(changes);
         Ok(())
     }
 
     pub fn remove_all(&mut self) -> Result<()> {
         trace!("Remove all");
-        self.queue.clear();
-
-        if self.queue.len() == 0 {
-            self.insert(self.default_meta_data.clone(), None)
-                .map_err(|e| anyhow!("Logic Error {}", e))?;
-        }
+        let mut changes = self
+            .queue
+            .drain(..)
+            .map(|car| Change::Removed(car.id))
+            .collect();
 
-        // NOTE: promote_changeはinsertで行われているので省略
+        self.fill_default_car(&mut changes);
+        self.promote_change(changes);
 
         Ok(())
     }
@@ -163,6 +206,7 @@ impl PendingCarQueue {
             .map(|meta| PendingCar {
                 id: nanoid!(),
                 meta,
+                rerun: false,
             })
             .collect::<Vec<PendingCar>>();
 
@@ -173,32 +217,44 @@ impl PendingCarQueue {
             bail!("Request includes invalid record!");
         }
 
-        if self.queue.len() == 0 {
-            self.insert(self.default_meta_data.clone(), None)
-                .map_err(|e| anyhow!("Logic Error {}", e))?;
-        }
+        let mut changes: Vec<Change<PendingCar>> = self
+            .queue
+            .iter()
+            .map(|car| Change::Removed(car.id.clone()))
+            .collect();
+
+        changes.extend(
+            new_records
+                .iter()
+                .enumerate()
+                .map(|(position, car)| Change::Inserted {
+                    position,
+                    item: car.clone(),
+                }),
+        );
 
         self.queue = new_records;
-        self.promote_change();
+        self.promote_change(changes);
+
         Ok(())
     }
 
-    pub fn watcher(&self) -> &tokio::sync::watch::Receiver<Vec<PendingCar>> {
-        &self.watcher
+    pub fn queue(&self) -> &Vec<PendingCar> {
+        &self.queue
     }
 
     fn find_car_index(&mut self, car_id: &str) -> Result<usize> {
-        if let Some(index) = self.queue.iter().position(|car| &car.id == car_id) {
+        if let Some(index) = self.queue.iter().position(|car| car.id == car_id) {
             Ok(index)
         } else {
             Err(anyhow!("No such car {}", car_id))
         }
     }
 
-    fn promote_change(&self) {
+    fn promote_change(&mut self, changes: Vec<Change<PendingCar>>) {
         trace!("Promoting change");
-        if let Err(error) = self.on_change.send(self.queue.clone()) {
-            error!("Failed to promote change. ({:?})", error);
+        if self.change_log.push(changes).is_some() {
+            self.event_bus.publish(Event::QueueChanged);
         }
     }
 
@@ -213,56 +269,122 @@ impl PendingCarQueue {
 
 }
 
-#[async_trait]
 impl crate::running_observer::NextCarQueue for PendingCarQueue {
-    async fn consume_next_car(&mut self) -> Option<MetaData> {
-        if self.queue.len() == 0 {
-            return None;
-        }
+    fn consume_next_car(&mut self) -> Option<MetaData> {
+        let car_id = self.queue.first()?.id.clone();
 
-        let consumed_meta = self.queue.remove(0).meta;
+        self.consume_car(&car_id)
+            .map_err(|e| error!("Logic Error {}", e))
+            .ok()
+    }
 
-        if self.queue.len() == 0 {
-            self.insert(self.default_meta_data.clone(), None)
-                .unwrap_or_else(|e| error!("Logic Error {}", e));
-        }
+    fn consume_car(&mut self, car_id: &str) -> Result<MetaData> {
+        let index = self.find_car_index(car_id)?;
+        let consumed = self.queue.remove(index);
 
-        self.promote_change();
+        let mut changes = vec![Change::Removed(consumed.id)];
+        self.fill_default_car(&mut changes);
+        self.promote_change(changes);
 
-        Some(consumed_meta)
+        Ok(consumed.meta)
+    }
+
+    fn requeue(&mut self, metas: Vec<MetaData>) -> Result<()> {
+        trace!("Requeueing");
+
+        let cars = metas
+            .into_iter()
+            .map(|meta| PendingCar {
+                id: nanoid!(),
+                meta,
+                rerun: true,
+            })
+            .collect();
+
+        self.insert_cars(cars, Some(0))
     }
 }
 
 pub mod server {
     use async_trait::async_trait;
     use log::trace;
-    use std::{pin::Pin, sync::Arc};
-    use tokio::sync::Mutex;
+    use std::pin::Pin;
     use tokio_stream::Stream;
     use tonic::{Request, Status};
 
-    use super::PendingCarQueue;
+    use super::PendingCar;
+    use crate::actor::CoreHandle;
+    use crate::change_log::{
+        server::{delta_stream, DeltaStream},
+        Change, Message,
+    };
+    use crate::event_bus::{changed, Event};
     use crate::proto::pending_car_queue::{self as proto, ReadAllReply};
 
+    impl From<&PendingCar> for proto::InsertedItem {
+        fn from(pending_car: &PendingCar) -> Self {
+            proto::InsertedItem {
+                id: pending_car.id.clone(),
+                meta: pending_car.meta.clone(),
+                rerun: pending_car.rerun,
+            }
+        }
+    }
+
+    impl From<&Change<PendingCar>> for proto::Change {
+        fn from(change: &Change<PendingCar>) -> Self {
+            use proto::change::Change as Kind;
+
+            proto::Change {
+                change: Some(match change {
+                    Change::Inserted { position, item } => Kind::Inserted(proto::Inserted {
+                        position: *position as u32,
+                        item: Some(item.into()),
+                    }),
+                    Change::Updated(item) => Kind::Updated(item.into()),
+                    Change::Removed(id) => Kind::Removed(id.clone()),
+                }),
+            }
+        }
+    }
+
+    fn to_delta_reply(epoch: &str, message: &Message<PendingCar>) -> proto::DeltaReply {
+        match message {
+            Message::Reset { revision, items } => proto::DeltaReply {
+                revision: *revision,
+                reset: true,
+                items: items.iter().map(Into::into).collect(),
+                changes: vec![],
+                epoch: epoch.to_string(),
+            },
+            Message::Delta(delta) => proto::DeltaReply {
+                revision: delta.revision,
+                reset: false,
+                items: vec![],
+                changes: delta.changes.iter().map(Into::into).collect(),
+                epoch: epoch.to_string(),
+            },
+        }
+    }
+
     #[async_trait]
-    impl proto::pending_car_queue_server::PendingCarQueue for Arc<Mutex<PendingCarQueue>> {
+    impl proto::pending_car_queue_server::PendingCarQueue for CoreHandle {
         async fn insert(
             &self,
             request: Request<proto::InsertRequest>,
         ) -> Result<tonic::Response<proto::CommandReply>, Status> {
-            let proto::InsertRequest { item, position } = request.get_ref();
+            let proto::InsertRequest { item, position } = request.into_inner();
 
-            let item = item.as_ref().ok_or(Status::invalid_argument(
+            let item = item.ok_or(Status::invalid_argument(
                 "InsertRequest property item is required!",
             ))?;
 
-            self.lock()
-                .await
-                .insert(
-                    item.meta.clone(),
-                    position.map(|position| position as usize),
-                )
-                .map_err(|e| Status::failed_precondition(e.to_string()))?;
+            self.run(move |core| {
+                core.pending_car_queue
+                    .insert(item.meta, position.map(|position| position as usize))
+            })
+            .await?
+            .map_err(|e| Status::failed_precondition(e.to_string()))?;
 
             Ok(tonic::Response::new(proto::CommandReply {}))
         }
@@ -271,11 +393,10 @@ pub mod server {
             &self,
             request: Request<proto::RemoveRequest>,
         ) -> Result<tonic::Response<proto::CommandReply>, Status> {
-            let proto::RemoveRequest { id } = request.get_ref();
+            let proto::RemoveRequest { id } = request.into_inner();
 
-            self.lock()
-                .await
-                .remove(id)
+            self.run(move |core| core.pending_car_queue.remove(&id))
+                .await?
                 .map_err(|e| Status::failed_precondition(e.to_string()))?;
 
             Ok(tonic::Response::new(proto::CommandReply {}))
@@ -285,15 +406,14 @@ pub mod server {
             &self,
             request: Request<proto::UpdateRequest>,
         ) -> Result<tonic::Response<proto::CommandReply>, Status> {
-            let proto::UpdateRequest { item } = request.get_ref();
+            let proto::UpdateRequest { item } = request.into_inner();
 
-            let item = item.as_ref().ok_or(Status::invalid_argument(
+            let item = item.ok_or(Status::invalid_argument(
                 "InsertRequest property item is required!",
             ))?;
 
-            self.lock()
-                .await
-                .update(&item.id, item.meta.clone())
+            self.run(move |core| core.pending_car_queue.update(&item.id, item.meta))
+                .await?
                 .map_err(|e| Status::failed_precondition(e.to_string()))?;
 
             Ok(tonic::Response::new(proto::CommandReply {}))
@@ -303,15 +423,16 @@ pub mod server {
             &self,
             request: Request<proto::InsertManyRequest>,
         ) -> Result<tonic::Response<proto::CommandReply>, Status> {
-            let proto::InsertManyRequest { item, position } = request.get_ref();
+            let proto::InsertManyRequest { item, position } = request.into_inner();
 
-            self.lock()
-                .await
-                .insert_many(
-                    item.iter().map(|item| item.meta.clone()),
+            self.run(move |core| {
+                core.pending_car_queue.insert_many(
+                    item.into_iter().map(|item| item.meta),
                     position.map(|pos| pos as usize),
                 )
-                .map_err(|e| Status::failed_precondition(e.to_string()))?;
+            })
+            .await?
+            .map_err(|e| Status::failed_precondition(e.to_string()))?;
 
             Ok(tonic::Response::new(proto::CommandReply {}))
         }
@@ -320,9 +441,8 @@ pub mod server {
             &self,
             _request: Request<proto::RemoveAllRequest>,
         ) -> Result<tonic::Response<proto::CommandReply>, Status> {
-            self.lock()
-                .await
-                .remove_all()
+            self.run(|core| core.pending_car_queue.remove_all())
+                .await?
                 .map_err(|e| Status::failed_precondition(e.to_string()))?;
 
             Ok(tonic::Response::new(proto::CommandReply {}))
@@ -332,12 +452,14 @@ pub mod server {
             &self,
             request: Request<proto::ReplaceAllRequest>,
         ) -> Result<tonic::Response<proto::CommandReply>, Status> {
-            let proto::ReplaceAllRequest { item } = request.get_ref();
+            let proto::ReplaceAllRequest { item } = request.into_inner();
 
-            self.lock()
-                .await
-                .replace(item.iter().map(|item| item.meta.clone()))
-                .map_err(|e| Status::failed_precondition(e.to_string()))?;
+            self.run(move |core| {
+                core.pending_car_queue
+                    .replace(item.into_iter().map(|item| item.meta))
+            })
+            .await?
+            .map_err(|e| Status::failed_precondition(e.to_string()))?;
 
             Ok(tonic::Response::new(proto::CommandReply {}))
         }
@@ -348,15 +470,8 @@ pub mod server {
         ) -> Result<tonic::Response<proto::ReadAllReply>, Status> {
             Ok(tonic::Response::new(proto::ReadAllReply {
                 item: self
-                    .lock()
-                    .await
-                    .queue
-                    .iter()
-                    .map(|item| proto::InsertedItem {
-                        id: item.id.clone(),
-                        meta: item.meta.clone(),
-                    })
-                    .collect(),
+                    .run(|core| core.pending_car_queue.queue.iter().map(Into::into).collect())
+                    .await?,
             }))
         }
 
@@ -368,22 +483,25 @@ pub mod server {
             _request: Request<proto::SubscribeChangeRequest>,
         ) -> Result<tonic::Response<Self::SubscribeChangeStream>, Status> {
             let (tx, rx) = tokio::sync::mpsc::channel(1);
-            let mut watcher = self.lock().await.watcher.clone();
+            let mut events = self.event_bus().subscribe();
+            let core = self.clone();
             tokio::spawn(async move {
                 trace!("Change receiver spawned");
-                while watcher.changed().await.is_ok() {
-                    trace!("change received!");
-                    let records = watcher.borrow().clone();
+                loop {
+                    let records = match core
+                        .run(|core| core.pending_car_queue.queue.clone())
+                        .await
+                    {
+                        Ok(records) => records,
+                        Err(stopped) => {
+                            let _ = tx.send(Err(stopped.into())).await;
+                            break;
+                        }
+                    };
 
                     match tx
                         .send(Result::<_, Status>::Ok(ReadAllReply {
-                            item: records
-                                .iter()
-                                .map(|item| proto::InsertedItem {
-                                    id: item.id.clone(),
-                                    meta: item.meta.clone(),
-                                })
-                                .collect(),
+                            item: records.iter().map(Into::into).collect(),
                         }))
                         .await
                     {
@@ -392,6 +510,11 @@ pub mod server {
                             break;
                         }
                     }
+
+                    if !changed(&mut events, Event::is_pending_car_queue_event).await {
+                        break;
+                    }
+                    trace!("change received!");
                 }
                 trace!("Change receiver closed");
             });
@@ -402,55 +525,75 @@ pub mod server {
                 Box::pin(out_stream) as Self::SubscribeChangeStream
             ))
         }
+
+        type SubscribeDeltaStream = DeltaStream<proto::DeltaReply>;
+
+        async fn subscribe_delta(
+            &self,
+            request: Request<proto::SubscribeDeltaRequest>,
+        ) -> Result<tonic::Response<Self::SubscribeDeltaStream>, Status> {
+            let proto::SubscribeDeltaRequest {
+                since_revision,
+                epoch,
+            } = request.into_inner();
+
+            let resumption = self
+                .run(move |core| {
+                    let queue = &core.pending_car_queue;
+                    queue.change_log.resume(&epoch, since_revision, &queue.queue)
+                })
+                .await?;
+
+            Ok(tonic::Response::new(delta_stream(resumption, to_delta_reply)))
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::{
-        config::{self, Config, RecordMetadata},
+        change_log::Change,
+        config::Config,
+        event_bus::{Event, EventBus},
         pending_car_queue::PendingCarQueue,
         running_observer::NextCarQueue,
     };
 
     fn setup() -> (PendingCarQueue,) {
-        let config = Config {
-            record: config::Record {
-                metadata: RecordMetadata {
-                    schema: serde_json::from_str(&r#"{"type": "string"}"#).unwrap(),
-                    default: serde_json::from_str(&r#""default_metadata""#).unwrap(),
-                },
-            },
-            ..Config::default()
-        };
+        let config = Config::for_test();
+
+        let mut queue = PendingCarQueue::new(&config, EventBus::new());
 
-        (PendingCarQueue::new(&config),)
+        // NOTE: Start without the default car.
+        queue.queue.clear();
+
+        (queue,)
     }
 
-    #[tokio::test]
-    async fn works_when_added() {
+    #[test]
+    fn works_when_added() {
         let mut queue = setup().0;
 
-        queue.insert("0".to_string(), None).unwrap();
-        queue.insert("1".to_string(), Some(1)).unwrap();
+        queue.insert(r#""0""#.to_string(), None).unwrap();
+        queue.insert(r#""1""#.to_string(), Some(1)).unwrap();
 
-        assert_eq!(queue.consume_next_car().await.unwrap(), "0".to_string());
-        assert_eq!(queue.consume_next_car().await.unwrap(), "1".to_string());
+        assert_eq!(queue.consume_next_car().unwrap(), r#""0""#.to_string());
+        assert_eq!(queue.consume_next_car().unwrap(), r#""1""#.to_string());
     }
 
-    #[tokio::test]
-    async fn error_when_added_with_too_large_index() {
+    #[test]
+    fn error_when_added_with_too_large_index() {
         let mut queue = setup().0;
 
-        queue.insert("10".to_string(), Some(1)).unwrap_err();
+        queue.insert(r#""10""#.to_string(), Some(1)).unwrap_err();
     }
 
-    #[tokio::test]
-    async fn works_when_removed() {
+    #[test]
+    fn works_when_removed() {
         let mut queue = setup().0;
 
-        queue.insert("0".to_string(), None).unwrap();
-        queue.insert("1".to_string(), Some(1)).unwrap();
+        queue.insert(r#""0""#.to_string(), None).unwrap();
+        queue.insert(r#""1""#.to_string(), Some(1)).unwrap();
 
         let id0 = queue.queue[0].id.clone();
         let id1 = queue.queue[1].id.clone();
@@ -459,12 +602,76 @@ mod tests {
         queue.remove(&id1).unwrap();
     }
 
-    #[tokio::test]
-    async fn error_when_removed_with_unknown_index() {
+    #[test]
+    fn error_when_removed_with_unknown_index() {
+        let mut queue = setup().0;
+
+        queue.insert(r#""10""#.to_string(), None).unwrap();
+
+        queue.remove("invalid_id").unwrap_err();
+    }
+
+    #[test]
+    fn records_removal_with_default_car() {
+        let mut queue = setup().0;
+
+        queue.insert(r#""0""#.to_string(), None).unwrap();
+        let id = queue.queue[0].id.clone();
+
+        let mut events = queue.event_bus.subscribe();
+
+        queue.consume_next_car().unwrap();
+
+        let deltas = queue.change_log.since(1).unwrap();
+
+        assert_eq!(deltas.len(), 1);
+        assert!(matches!(
+            &deltas[0].changes[..],
+            [Change::Removed(removed), Change::Inserted { position: 0, item }]
+                if removed == &id && item.meta == r#""default_metadata""#
+        ));
+
+        assert_eq!(events.try_recv().unwrap(), Event::QueueChanged);
+        events.try_recv().unwrap_err();
+    }
+
+    #[test]
+    fn consumes_specified_car() {
+        let mut queue = setup().0;
+
+        queue.insert(r#""0""#.to_string(), None).unwrap();
+        queue.insert(r#""1""#.to_string(), None).unwrap();
+        let id1 = queue.queue[1].id.clone();
+
+        assert_eq!(queue.consume_car(&id1).unwrap(), r#""1""#.to_string());
+        queue.consume_car(&id1).unwrap_err();
+        assert_eq!(queue.consume_next_car().unwrap(), r#""0""#.to_string());
+    }
+
+    #[test]
+    fn requeues_at_head_as_rerun() {
+        let mut queue = setup().0;
+
+        queue.insert(r#""0""#.to_string(), None).unwrap();
+        queue
+            .requeue(vec![r#""1""#.to_string(), r#""2""#.to_string()])
+            .unwrap();
+
+        assert_eq!(queue.queue[0].meta, r#""1""#);
+        assert_eq!(queue.queue[1].meta, r#""2""#);
+        assert!(queue.queue[0].rerun);
+        assert!(queue.queue[1].rerun);
+        assert!(!queue.queue[2].rerun);
+    }
+
+    #[test]
+    fn requeues_nothing_if_any_car_is_invalid() {
         let mut queue = setup().0;
 
-        queue.insert("10".to_string(), None).unwrap();
+        queue
+            .requeue(vec![r#""1""#.to_string(), "1".to_string()])
+            .unwrap_err();
 
-        queue.remove(&"invalid_id").unwrap_err();
+        assert!(queue.queue.is_empty());
     }
 }
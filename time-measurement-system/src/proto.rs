@@ -1,13 +1,23 @@
-pub mod pending_car_queue {
-    tonic::include_proto!("has.pendingcarqueue");
-}
-pub mod records {
-    tonic::include_proto!("has.records");
-}
-pub mod running_observer {
-    tonic::include_proto!("has.runningobserver");
+// NOTE: Nested by package so that generated references between packages (`super::...`) resolve.
+pub mod has {
+    pub mod pendingcarqueue {
+        tonic::include_proto!("has.pendingcarqueue");
+    }
+    pub mod records {
+        tonic::include_proto!("has.records");
+    }
+    pub mod runningobserver {
+        tonic::include_proto!("has.runningobserver");
+    }
+    pub mod aggrigatedchangebroadcaster {
+        tonic::include_proto!("has.aggrigatedchangebroadcaster");
+    }
 }
 
-pub mod aggrigated_change_broadcaster {
-    tonic::include_proto!("has.aggrigatedchangebroadcaster");
-}
+pub use has::aggrigatedchangebroadcaster as aggrigated_change_broadcaster;
+pub use has::pendingcarqueue as pending_car_queue;
+pub use has::records;
+pub use has::runningobserver as running_observer;
+
+pub const FILE_DESCRIPTOR_SET: &[u8] =
+    tonic::include_file_descriptor_set!("time_measurement_system_descriptor");
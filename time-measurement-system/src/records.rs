@@ -1,13 +1,17 @@
 use anyhow::anyhow;
 use anyhow::bail;
 use anyhow::Result;
-use async_trait::async_trait;
 use jsonschema::JSONSchema;
 use log::{debug, error};
 use nanoid::nanoid;
 
+use crate::change_log::{Change, ChangeLog};
+use crate::config::Display;
+use crate::event_bus::{Event, EventBus};
 use crate::prelude::*;
 use crate::running_observer;
+use crate::source::Source;
+use crate::units::format_duration;
 use crate::Config;
 
 #[derive(Clone, Debug)]
@@ -15,68 +19,99 @@ pub struct Record {
     pub record_id: String,
     pub duration: Duration,
     pub meta: String,
+    /// `duration` formatted with the configured precision and rounding.
+    pub display: String,
+    pub start_source: Source,
+    pub stop_source: Source,
+}
+
+impl Record {
+    /// Whether a marshal took the start or the stop instead of a sensor.
+    pub fn is_hand_timed(&self) -> bool {
+        self.start_source.is_hand_timed() || self.stop_source.is_hand_timed()
+    }
 }
 
 pub struct Records {
     records: Vec<Record>,
+    display: Display,
     meta_schema: JSONSchema,
-    on_change: tokio::sync::watch::Sender<Vec<Record>>,
-    watcher: tokio::sync::watch::Receiver<Vec<Record>>,
+    event_bus: EventBus,
+    change_log: ChangeLog<Record>,
 }
 
 impl Records {
-    pub fn new(config: &Config) -> Self {
-        let records = Vec::new();
-        let (on_change, watcher) = tokio::sync::watch::channel(records.clone());
+    pub fn new(config: &Config, event_bus: EventBus) -> Self {
         Self {
-            records,
+            records: Vec::new(),
+            display: config.display,
             meta_schema: JSONSchema::compile(&config.record.metadata.schema)
                 .unwrap_or_else(|e| panic!("Invalid metadata schema! {:?}", e)),
-            on_change,
-            watcher,
+            event_bus,
+            change_log: ChangeLog::new(),
         }
     }
 
-    pub fn add(&mut self, duration: &Duration, meta: &str) -> Result<()> {
+    pub fn add(
+        &mut self,
+        duration: &Duration,
+        meta: &str,
+        start_source: Source,
+        stop_source: Source,
+    ) -> Result<()> {
         let record = Record {
             record_id: nanoid!(),
-            duration: duration.clone(),
+            duration: *duration,
             meta: meta.to_string(),
+            display: format_duration(*duration, &self.display),
+            start_source,
+            stop_source,
         };
 
         self.validate_record(&record)?;
 
         debug!("An Record added. ({:?})", record);
 
-        self.records.push(record);
+        self.records.push(record.clone());
 
-        self.promote_change();
+        self.promote_change(vec![Change::Inserted {
+            position: self.records.len() - 1,
+            item: record,
+        }]);
+        self.event_bus.publish(Event::RecordAdded);
         Ok(())
     }
 
+    /// Sources are kept as recorded.
     pub fn update(&mut self, record_id: &str, duration: Duration, meta: &str) -> Result<()> {
-        let new_record = Record {
-            record_id: record_id.to_string(),
-            duration,
-            meta: meta.to_string(),
-        };
-        if let Some(index) = self.find_record_index(&new_record.record_id) {
+        if let Some(index) = self.find_record_index(record_id) {
+            let new_record = Record {
+                record_id: record_id.to_string(),
+                duration,
+                meta: meta.to_string(),
+                display: format_duration(duration, &self.display),
+                start_source: self.records[index].start_source.clone(),
+                stop_source: self.records[index].stop_source.clone(),
+            };
+
             self.validate_record(&new_record)?;
-            self.records[index] = new_record;
+            self.records[index] = new_record.clone();
 
-            self.promote_change();
+            self.promote_change(vec![Change::Updated(new_record)]);
+            self.event_bus.publish(Event::RecordUpdated);
             Ok(())
         } else {
-            bail!("Specified record {:?} was not found.", new_record.record_id);
+            bail!("Specified record {:?} was not found.", record_id);
         }
     }
 
     // NOTE: 論理削除を検討
     pub fn remove(&mut self, record_id: &str) -> Result<()> {
-        if let Some(index) = self.find_record_index(&record_id) {
+        if let Some(index) = self.find_record_index(record_id) {
             self.records.remove(index);
 
-            self.promote_change();
+            self.promote_change(vec![Change::Removed(record_id.to_string())]);
+            self.event_bus.publish(Event::RecordRemoved);
             Ok(())
         } else {
             bail!("Specified record {:?} was not found", record_id);
@@ -84,20 +119,23 @@ impl Records {
     }
 
     pub fn remove_all(&mut self) -> Result<()> {
-        self.records.clear();
-
-        self.promote_change();
+        let changes = self
+            .records
+            .drain(..)
+            .map(|record| Change::Removed(record.record_id))
+            .collect();
+
+        self.promote_change(changes);
+        self.event_bus.publish(Event::RecordsCleared);
         Ok(())
     }
 
-    pub fn watcher(&self) -> &tokio::sync::watch::Receiver<Vec<Record>> {
-        &self.watcher
+    pub fn records(&self) -> &Vec<Record> {
+        &self.records
     }
 
-    fn promote_change(&self) {
-        if let Err(error) = self.on_change.send(self.records.clone()) {
-            error!("Failed to promote change. ({:?})", error);
-        }
+    fn promote_change(&mut self, changes: Vec<Change<Record>>) {
+        self.change_log.push(changes);
     }
 
     fn validate_record(&mut self, record: &Record) -> Result<()> {
@@ -117,32 +155,98 @@ impl Records {
 pub mod server {
     use async_trait::async_trait;
     use log::trace;
-    use std::{pin::Pin, sync::Arc};
-    use tokio::sync::Mutex;
+    use std::pin::Pin;
     use tokio_stream::Stream;
     use tonic::{Request, Status};
 
-    use super::Records;
+    use super::Record;
+    use crate::actor::CoreHandle;
+    use crate::change_log::{
+        server::{delta_stream, DeltaStream},
+        Change, Message,
+    };
+    use crate::event_bus::{changed, Event};
     use crate::proto::records::{self as proto, ReadAllReply};
+    use crate::source::Source;
+    use crate::units;
+
+    impl From<&Record> for proto::InsertedItem {
+        fn from(record: &Record) -> Self {
+            proto::InsertedItem {
+                id: record.record_id.clone(),
+                time: units::to_millis(record.duration),
+                duration: Some(units::duration_to_proto(record.duration)),
+                meta: record.meta.clone(),
+                display: record.display.clone(),
+                start_source: Some((&record.start_source).into()),
+                stop_source: Some((&record.stop_source).into()),
+                hand_timed: record.is_hand_timed(),
+            }
+        }
+    }
+
+    impl From<&Change<Record>> for proto::Change {
+        fn from(change: &Change<Record>) -> Self {
+            use proto::change::Change as Kind;
+
+            proto::Change {
+                change: Some(match change {
+                    Change::Inserted { position, item } => Kind::Inserted(proto::Inserted {
+                        position: *position as u32,
+                        item: Some(item.into()),
+                    }),
+                    Change::Updated(item) => Kind::Updated(item.into()),
+                    Change::Removed(id) => Kind::Removed(id.clone()),
+                }),
+            }
+        }
+    }
+
+    fn to_delta_reply(epoch: &str, message: &Message<Record>) -> proto::DeltaReply {
+        match message {
+            Message::Reset { revision, items } => proto::DeltaReply {
+                revision: *revision,
+                reset: true,
+                items: items.iter().map(Into::into).collect(),
+                changes: vec![],
+                epoch: epoch.to_string(),
+            },
+            Message::Delta(delta) => proto::DeltaReply {
+                revision: delta.revision,
+                reset: false,
+                items: vec![],
+                changes: delta.changes.iter().map(Into::into).collect(),
+                epoch: epoch.to_string(),
+            },
+        }
+    }
 
     #[async_trait]
-    impl proto::records_server::Records for Arc<Mutex<Records>> {
+    impl proto::records_server::Records for CoreHandle {
         type SubscribeChangeStream =
             Pin<Box<dyn Stream<Item = Result<proto::ReadAllReply, Status>> + Send>>;
 
+        type SubscribeDeltaStream = DeltaStream<proto::DeltaReply>;
+
         async fn insert(
             &self,
             request: Request<proto::InsertRequest>,
         ) -> Result<tonic::Response<proto::CommandReply>, Status> {
-            let proto::InsertRequest { item } = request.get_ref();
+            let proto::InsertRequest { item } = request.into_inner();
 
-            let item = item.as_ref().ok_or(Status::invalid_argument(
+            let item = item.ok_or(Status::invalid_argument(
                 "InsertRequest property item is required!",
             ))?;
 
-            self.lock()
-                .await
-                .add(&item.time, &item.meta)
+            let duration = units::duration_from_proto(item.time, item.duration);
+            let start_source = Source::try_from(item.start_source)?;
+            let stop_source = Source::try_from(item.stop_source)?;
+
+            self.run(move |core| {
+                core.records
+                    .add(&duration, &item.meta, start_source, stop_source)
+            })
+                .await?
                 .map_err(|e| Status::failed_precondition(e.to_string()))?;
 
             Ok(tonic::Response::new(proto::CommandReply {}))
@@ -152,11 +256,10 @@ pub mod server {
             &self,
             request: Request<proto::RemoveRequest>,
         ) -> Result<tonic::Response<proto::CommandReply>, Status> {
-            let proto::RemoveRequest { id } = request.get_ref();
+            let proto::RemoveRequest { id } = request.into_inner();
 
-            self.lock()
-                .await
-                .remove(id)
+            self.run(move |core| core.records.remove(&id))
+                .await?
                 .map_err(|e| Status::failed_precondition(e.to_string()))?;
 
             Ok(tonic::Response::new(proto::CommandReply {}))
@@ -166,15 +269,16 @@ pub mod server {
             &self,
             request: Request<proto::UpdateRequest>,
         ) -> Result<tonic::Response<proto::CommandReply>, Status> {
-            let proto::UpdateRequest { item } = request.get_ref();
+            let proto::UpdateRequest { item } = request.into_inner();
 
-            let item = item.as_ref().ok_or(Status::invalid_argument(
+            let item = item.ok_or(Status::invalid_argument(
                 "InsertRequest property item is required!",
             ))?;
 
-            self.lock()
-                .await
-                .update(&item.id, item.time, &item.meta)
+            let duration = units::duration_from_proto(item.time, item.duration);
+
+            self.run(move |core| core.records.update(&item.id, duration, &item.meta))
+                .await?
                 .map_err(|e| Status::failed_precondition(e.to_string()))?;
 
             Ok(tonic::Response::new(proto::CommandReply {}))
@@ -184,9 +288,8 @@ pub mod server {
             &self,
             _request: Request<proto::RemoveAllRequest>,
         ) -> Result<tonic::Response<proto::CommandReply>, Status> {
-            self.lock()
-                .await
-                .remove_all()
+            self.run(|core| core.records.remove_all())
+                .await?
                 .map_err(|e| Status::failed_precondition(e.to_string()))?;
 
             Ok(tonic::Response::new(proto::CommandReply {}))
@@ -198,16 +301,8 @@ pub mod server {
         ) -> Result<tonic::Response<proto::ReadAllReply>, Status> {
             Ok(tonic::Response::new(proto::ReadAllReply {
                 item: self
-                    .lock()
-                    .await
-                    .records
-                    .iter()
-                    .map(|item| proto::InsertedItem {
-                        id: item.record_id.clone(),
-                        time: item.duration,
-                        meta: item.meta.clone(),
-                    })
-                    .collect(),
+                    .run(|core| core.records.records.iter().map(Into::into).collect())
+                    .await?,
             }))
         }
 
@@ -216,22 +311,21 @@ pub mod server {
             _request: Request<proto::SubscribeChangeRequest>,
         ) -> Result<tonic::Response<Self::SubscribeChangeStream>, Status> {
             let (tx, rx) = tokio::sync::mpsc::channel(1);
-            let mut watcher = self.lock().await.watcher.clone();
+            let mut events = self.event_bus().subscribe();
+            let core = self.clone();
             tokio::spawn(async move {
-                while watcher.changed().await.is_ok() {
-                    trace!("change received!");
-                    let records = watcher.borrow().clone();
+                loop {
+                    let records = match core.run(|core| core.records.records.clone()).await {
+                        Ok(records) => records,
+                        Err(stopped) => {
+                            let _ = tx.send(Err(stopped.into())).await;
+                            break;
+                        }
+                    };
 
                     match tx
                         .send(Result::<_, Status>::Ok(ReadAllReply {
-                            item: records
-                                .iter()
-                                .map(|item| proto::InsertedItem {
-                                    id: item.record_id.clone(),
-                                    time: item.duration,
-                                    meta: item.meta.clone(),
-                                })
-                                .collect(),
+                            item: records.iter().map(Into::into).collect(),
                         }))
                         .await
                     {
@@ -240,6 +334,11 @@ pub mod server {
                             break;
                         }
                     }
+
+                    if !changed(&mut events, Event::is_records_event).await {
+                        break;
+                    }
+                    trace!("change received!");
                 }
             });
 
@@ -249,14 +348,37 @@ pub mod server {
                 Box::pin(out_stream) as Self::SubscribeChangeStream
             ))
         }
+
+        async fn subscribe_delta(
+            &self,
+            request: Request<proto::SubscribeDeltaRequest>,
+        ) -> Result<tonic::Response<Self::SubscribeDeltaStream>, Status> {
+            let proto::SubscribeDeltaRequest {
+                since_revision,
+                epoch,
+            } = request.into_inner();
+
+            let resumption = self
+                .run(move |core| {
+                    let records = &core.records;
+                    records.change_log.resume(&epoch, since_revision, &records.records)
+                })
+                .await?;
+
+            Ok(tonic::Response::new(delta_stream(resumption, to_delta_reply)))
+        }
     }
 }
 
-#[async_trait]
 impl running_observer::RecordService for Records {
-    async fn record(&mut self, record: running_observer::Record) {
+    fn record(&mut self, record: running_observer::Record) {
         debug!("An record received via internal interface. ({:?})", &record);
-        if let Err(error) = self.add(&record.duration, &record.meta) {
+        if let Err(error) = self.add(
+            &record.duration,
+            &record.meta,
+            record.start_source.clone(),
+            record.stop_source.clone(),
+        ) {
             error!(
                 "Failed to insert a record ({:?}) due to {:?}",
                 &record, error
@@ -266,4 +388,44 @@ impl running_observer::RecordService for Records {
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use tokio_stream::StreamExt;
+    use tonic::Request;
+
+    use crate::actor::{Core, CoreHandle};
+    use crate::config::Config;
+    use crate::event_bus::EventBus;
+    use crate::proto::records::{records_server::Records as _, SubscribeChangeRequest};
+    use crate::source::Source;
+
+    #[tokio::test]
+    async fn subscription_starts_with_current_state() {
+        let config = Config::for_test();
+
+        let core = CoreHandle::spawn(Core::new(&config, EventBus::new()));
+        core.run(|core| {
+            core.records
+                .add(&10, r#""0""#, Source::Unspecified, Source::Unspecified)
+        })
+            .await
+            .unwrap()
+            .unwrap();
+
+        let mut stream = core
+            .subscribe_change(Request::new(SubscribeChangeRequest {}))
+            .await
+            .unwrap()
+            .into_inner();
+
+        let reply = stream.next().await.unwrap().unwrap();
+        assert_eq!(reply.item.len(), 1);
+
+        core.run(|core| core.records.remove_all())
+            .await
+            .unwrap()
+            .unwrap();
+
+        let reply = stream.next().await.unwrap().unwrap();
+        assert_eq!(reply.item.len(), 0);
+    }
+}
@@ -1,54 +1,63 @@
 use anyhow::{anyhow, bail, Result};
-use async_trait::async_trait;
 use jsonschema::{JSONSchema, ValidationError};
 use log::{debug, error, trace};
 use nanoid::nanoid;
-use std::sync::Arc;
-use tokio::sync::Mutex;
 
-use crate::{prelude::*, Config};
+use crate::{
+    change_log::{Change, ChangeLog},
+    config::{CourseMode, RunTimeout, TimeoutAction},
+    course::CourseState,
+    event_bus::{Event, EventBus},
+    prelude::*,
+    source::Source,
+    units, Config,
+};
 
 #[derive(Clone, Debug)]
 pub struct RunningCar {
     car_id: RunningCarId,
     start_at: TimeStamp,
     meta: String,
+    /// Set once the car exceeded the max run time with `TimeoutAction::Flag`.
+    timed_out: bool,
+    start_source: Source,
 }
 
-#[async_trait]
 pub trait NextCarQueue {
-    async fn consume_next_car(&mut self) -> Option<MetaData>;
+    fn consume_next_car(&mut self) -> Option<MetaData>;
+    /// Removes the car `car_id` wherever it is in the queue.
+    fn consume_car(&mut self, car_id: &str) -> Result<MetaData>;
+    /// Puts cars back at the head of the queue in the given order to run again. Nothing is
+    /// requeued if any of them is rejected.
+    fn requeue(&mut self, metas: Vec<MetaData>) -> Result<()>;
 }
 
 #[derive(Clone, Debug)]
 pub struct Record {
     pub duration: Duration,
     pub meta: String,
+    pub start_source: Source,
+    pub stop_source: Source,
 }
 
-#[async_trait]
 pub trait RecordService {
-    async fn record(&mut self, record: Record);
+    fn record(&mut self, record: Record);
 }
 
 pub struct RunningObserver {
     running_car: Vec<RunningCar>,
+    course_state: CourseState,
+    mode: CourseMode,
+    max_cars_on_course: Option<usize>,
+    timeout: Option<RunTimeout>,
     meta_schema: JSONSchema,
     default_meta_data: String,
-    next_car_queue: Arc<Mutex<dyn NextCarQueue + Send>>,
-    record_service: Arc<Mutex<dyn RecordService + Send>>,
-    on_change: tokio::sync::watch::Sender<Vec<RunningCar>>,
-    watcher: tokio::sync::watch::Receiver<Vec<RunningCar>>,
+    event_bus: EventBus,
+    change_log: ChangeLog<RunningCar>,
 }
 
 impl RunningObserver {
-    pub fn new(
-        config: &Config,
-        next_car_queue: Arc<Mutex<dyn NextCarQueue + Send>>,
-        record_service: Arc<Mutex<dyn RecordService + Send>>,
-    ) -> RunningObserver {
-        let running_car = Vec::new();
-        let (on_change, watcher) = tokio::sync::watch::channel(running_car.clone());
+    pub fn new(config: &Config, event_bus: EventBus) -> RunningObserver {
         let meta_schema = JSONSchema::compile(&config.record.metadata.schema)
             .unwrap_or_else(|e| panic!("Invalid metadata schema! ({:?})", e));
 
@@ -61,35 +70,106 @@ impl RunningObserver {
                 )
             });
 
-        RunningObserver {
-            next_car_queue,
-            running_car,
-            record_service,
+        let max_cars_on_course = match (config.course.mode, config.course.max_cars_on_course) {
+            (CourseMode::Flip, None | Some(1)) => Some(1),
+            (CourseMode::Flip, Some(max)) => {
+                panic!(
+                    "Flip mode allows only 1 car on course but {} was configured!",
+                    max
+                )
+            }
+            (CourseMode::Multi, Some(0)) => panic!("No car can start with max_cars_on_course 0!"),
+            (CourseMode::Multi, max) => max,
+        };
+
+        let mut running_observer = RunningObserver {
+            running_car: Vec::new(),
+            course_state: CourseState::default(),
+            mode: config.course.mode,
+            max_cars_on_course,
+            timeout: config.course.timeout,
             meta_schema,
-            on_change,
-            watcher,
+            event_bus,
+            change_log: ChangeLog::new(),
             default_meta_data: config.record.metadata.default.to_string(),
+        };
+
+        if let Some(RunTimeout {
+            action: TimeoutAction::Dnf,
+            ..
+        }) = running_observer.timeout
+        {
+            let default_meta_data = running_observer.default_meta_data.clone();
+            running_observer
+                .with_status(&default_meta_data, "DNF")
+                .unwrap_or_else(|e| {
+                    panic!(
+                        "Timeout action dnf needs metadata objects accepting status \"DNF\"! ({:?})",
+                        e
+                    )
+                });
         }
+
+        running_observer
     }
 
-    pub async fn start(&mut self, timestamp: TimeStamp) -> Result<()> {
+    pub fn start(
+        &mut self,
+        timestamp: TimeStamp,
+        source: Source,
+        next_car_queue: &mut dyn NextCarQueue,
+    ) -> Result<()> {
         debug!("Running start at {:?}", timestamp);
-        let next_car_metadata = self.next_car_queue.lock().await.consume_next_car().await;
+        self.ensure_start_allowed()?;
+
+        let meta = next_car_queue
+            .consume_next_car()
+            .unwrap_or_else(|| self.default_meta_data.clone());
+
+        self.start_with(timestamp, meta, source)
+    }
 
-        self.running_car.push(RunningCar {
+    /// Starts the pending car `pending_car_id` instead of the head of the queue.
+    pub fn start_car(
+        &mut self,
+        timestamp: TimeStamp,
+        pending_car_id: &str,
+        source: Source,
+        next_car_queue: &mut dyn NextCarQueue,
+    ) -> Result<()> {
+        debug!("Running start of {:?} at {:?}", pending_car_id, timestamp);
+        self.ensure_start_allowed()?;
+
+        let meta = next_car_queue.consume_car(pending_car_id)?;
+
+        self.start_with(timestamp, meta, source)
+    }
+
+    fn start_with(&mut self, timestamp: TimeStamp, meta: MetaData, source: Source) -> Result<()> {
+        let running_car = RunningCar {
             car_id: nanoid!(),
             start_at: timestamp,
-            meta: next_car_metadata.unwrap_or_else(|| self.default_meta_data.clone()),
-        });
+            meta,
+            timed_out: false,
+            start_source: source,
+        };
 
-        self.promote_change();
+        self.running_car.push(running_car.clone());
+
+        self.promote_change(vec![Change::Inserted {
+            position: self.running_car.len() - 1,
+            item: running_car,
+        }]);
+        self.event_bus.publish(Event::CarStarted);
         Ok(())
     }
 
-    pub async fn stop(
+    pub fn stop(
         &mut self,
         timestamp: TimeStamp,
         car_id: &Option<RunningCarId>,
+        source: Source,
+        record_service: &mut dyn RecordService,
     ) -> Result<()> {
         trace!(
             "Stop requested at {:?} and car_id was {:?}",
@@ -97,15 +177,34 @@ impl RunningObserver {
             car_id
         );
 
-        if self.running_car.len() == 0 {
+        if self.running_car.is_empty() {
             bail!("No one running");
         }
 
+        // NOTE: A timed out car is likely broken down on course, so the trigger is for the next one.
         let car_to_stop = match car_id {
             Some(car_id) => self.find_car_index(car_id)?,
-            None => 0,
+            None => self
+                .running_car
+                .iter()
+                .position(|running_car| !running_car.timed_out)
+                .ok_or(anyhow!(
+                    "Only timed out cars are running, specify the car to stop"
+                ))?,
         };
 
+        self.stop_at(car_to_stop, timestamp, source, record_service);
+        Ok(())
+    }
+
+    /// Removes and records the running car at `car_to_stop`, which must exist.
+    fn stop_at(
+        &mut self,
+        car_to_stop: usize,
+        timestamp: TimeStamp,
+        source: Source,
+        record_service: &mut dyn RecordService,
+    ) {
         let stopped_car = self.running_car.remove(car_to_stop);
 
         debug!(
@@ -115,39 +214,85 @@ impl RunningObserver {
 
         let duration: Duration = timestamp - stopped_car.start_at;
 
-        self.record_service
-            .lock()
-            .await
-            .record(Record {
-                duration,
-                meta: stopped_car.meta,
-            })
-            .await;
+        record_service.record(Record {
+            duration,
+            meta: stopped_car.meta.clone(),
+            start_source: stopped_car.start_source.clone(),
+            stop_source: source,
+        });
 
         trace!("Done stop process.");
 
-        self.promote_change();
+        self.promote_change(vec![Change::Removed(stopped_car.car_id)]);
+        self.event_bus.publish(Event::CarStopped);
+    }
+
+    /// Removes a running car without recording it, optionally putting it back to the queue.
+    pub fn cancel(
+        &mut self,
+        timestamp: TimeStamp,
+        car_id: &Option<RunningCarId>,
+        requeue: bool,
+        next_car_queue: &mut dyn NextCarQueue,
+    ) -> Result<()> {
+        trace!(
+            "Cancel requested at {:?} and car_id was {:?}",
+            timestamp,
+            car_id
+        );
+
+        if self.running_car.is_empty() {
+            bail!("No one running");
+        }
+
+        let car_to_cancel = match car_id {
+            Some(car_id) => self.find_car_index(car_id)?,
+            None => 0,
+        };
+
+        if requeue {
+            next_car_queue.requeue(vec![self.running_car[car_to_cancel].meta.clone()])?;
+        }
+
+        let cancelled_car = self.running_car.remove(car_to_cancel);
+
+        debug!(
+            "Running cancelled at {:?} and index {:?} was removed. meta: {:?}",
+            timestamp, car_to_cancel, cancelled_car.meta
+        );
+
+        self.promote_change(vec![Change::Removed(cancelled_car.car_id)]);
+        self.event_bus.publish(Event::CarCancelled);
         Ok(())
     }
 
-    pub async fn flip_start_or_stop(&mut self, timestamp: TimeStamp) -> Result<()> {
+    pub fn flip_start_or_stop(
+        &mut self,
+        timestamp: TimeStamp,
+        source: Source,
+        next_car_queue: &mut dyn NextCarQueue,
+        record_service: &mut dyn RecordService,
+    ) -> Result<()> {
         trace!("Flipping start and stop");
-        if self.running_car.len() == 0 {
+        if self.mode != CourseMode::Flip {
+            bail!(
+                "Flipping is not available in {:?} mode, use Start and Stop",
+                self.mode
+            );
+        }
+
+        if self.cars_on_course() == 0 {
             debug!("Nobody running so starting.");
-            self.start(timestamp).await?;
-            self.promote_change();
-            Ok(())
+            self.start(timestamp, source, next_car_queue)
         } else {
             debug!("Someone running so stopping.");
-            self.stop(timestamp, &None).await?;
-            self.promote_change();
-            Ok(())
+            self.stop(timestamp, &None, source, record_service)
         }
     }
 
-    pub async fn update_metadata(
+    pub fn update_metadata(
         &mut self,
-        timestamp: TimeStamp,
+        _timestamp: TimeStamp,
         car_id: &RunningCarId,
         metadata: String,
     ) -> Result<()> {
@@ -156,17 +301,154 @@ impl RunningObserver {
         self.validate_metadata(&metadata)?;
 
         let car_index = self.find_car_index(car_id)?;
-        self.running_car
+        let running_car = self
+            .running_car
             .get_mut(car_index)
-            .ok_or(anyhow!("Logic Error"))?
-            .meta = metadata;
+            .ok_or(anyhow!("Logic Error"))?;
+
+        running_car.meta = metadata;
+
+        let running_car = running_car.clone();
+        self.promote_change(vec![Change::Updated(running_car)]);
+        self.event_bus.publish(Event::RunningCarUpdated);
+        Ok(())
+    }
+
+    /// Changes the course state. Running cars are cancelled on red flag, and put back to the
+    /// queue to run again if `requeue` is set.
+    pub fn set_course_state(
+        &mut self,
+        timestamp: TimeStamp,
+        state: CourseState,
+        requeue: bool,
+        next_car_queue: &mut dyn NextCarQueue,
+    ) -> Result<()> {
+        debug!("Course state {:?} requested at {:?}", state, timestamp);
+
+        let state = self.course_state.transition(state)?;
+
+        if state == CourseState::Closed && !self.running_car.is_empty() {
+            bail!("Can not close the course while cars are running");
+        }
+
+        if state == CourseState::RedFlag && !self.running_car.is_empty() {
+            // NOTE: Requeue first so that a rejection leaves the course untouched.
+            if requeue {
+                next_car_queue.requeue(
+                    self.running_car
+                        .iter()
+                        .map(|running_car| running_car.meta.clone())
+                        .collect(),
+                )?;
+            }
+
+            let cancelled_cars: Vec<RunningCar> = self.running_car.drain(..).collect();
+
+            debug!(
+                "Running cancelled at {:?} by red flag. {} cars were removed",
+                timestamp,
+                cancelled_cars.len()
+            );
+
+            self.promote_change(
+                cancelled_cars
+                    .iter()
+                    .map(|car| Change::Removed(car.car_id.clone()))
+                    .collect(),
+            );
+            self.event_bus.publish(Event::CarCancelled);
+        }
+
+        self.course_state = state;
+        self.event_bus.publish(Event::CourseStateChanged);
+        Ok(())
+    }
+
+    /// Applies the configured timeout action to cars running longer than the max run time.
+    pub fn expire(&mut self, now: TimeStamp, record_service: &mut dyn RecordService) -> Result<()> {
+        let Some(timeout) = self.timeout else {
+            return Ok(());
+        };
+        let max_run_time = units::from_millis(timeout.max_run_time_ms as i64);
+
+        let expired: Vec<RunningCar> = self
+            .running_car
+            .iter()
+            .filter(|running_car| {
+                !running_car.timed_out && now - running_car.start_at >= max_run_time
+            })
+            .cloned()
+            .collect();
+
+        for running_car in expired {
+            debug!("Car {:?} exceeded the max run time", running_car.car_id);
+
+            if timeout.action == TimeoutAction::Dnf {
+                match self.with_status(&running_car.meta, "DNF") {
+                    Ok(meta) => {
+                        // NOTE: The car leaves with the DNF status, so it is not published as an
+                        // update of its own.
+                        let car_index = self.find_car_index(&running_car.car_id)?;
+                        self.running_car[car_index].meta = meta;
+                        self.stop_at(car_index, now, Source::Timeout, record_service);
+                        continue;
+                    }
+                    Err(error) => error!(
+                        "Failed to set DNF to {:?} so flagging instead. {:?}",
+                        running_car.meta, error
+                    ),
+                }
+            }
+
+            self.flag_timed_out(&running_car.car_id)?;
+        }
 
-        self.promote_change();
         Ok(())
     }
 
-    pub fn watcher(&self) -> &tokio::sync::watch::Receiver<Vec<RunningCar>> {
-        &self.watcher
+    /// When the next running car exceeds the max run time.
+    pub fn next_timeout(&self) -> Option<TimeStamp> {
+        let max_run_time = units::from_millis(self.timeout?.max_run_time_ms as i64);
+
+        self.running_car
+            .iter()
+            .filter(|running_car| !running_car.timed_out)
+            .map(|running_car| running_car.start_at + max_run_time)
+            .min()
+    }
+
+    pub fn running_car(&self) -> &Vec<RunningCar> {
+        &self.running_car
+    }
+
+    pub fn course_state(&self) -> CourseState {
+        self.course_state
+    }
+
+    fn ensure_start_allowed(&self) -> Result<()> {
+        if !self.course_state.allows_start() {
+            bail!("Course is {:?}", self.course_state);
+        }
+
+        if let Some(max) = self.max_cars_on_course {
+            if self.cars_on_course() >= max {
+                bail!(
+                    "Course is full ({} of {} cars running)",
+                    self.cars_on_course(),
+                    max
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Running cars except timed out ones, which are left on course only to be cleared by hand.
+    fn cars_on_course(&self) -> usize {
+        self.running_car
+            .iter()
+            .filter(|running_car| !running_car.timed_out)
+            .count()
     }
 
     fn find_car_index(&mut self, car_id: &RunningCarId) -> Result<usize> {
@@ -181,48 +463,147 @@ impl RunningObserver {
         }
     }
 
+    fn flag_timed_out(&mut self, car_id: &RunningCarId) -> Result<()> {
+        let car_index = self.find_car_index(car_id)?;
+        let running_car = &mut self.running_car[car_index];
+        running_car.timed_out = true;
+
+        let running_car = running_car.clone();
+        self.promote_change(vec![Change::Updated(running_car)]);
+        self.event_bus.publish(Event::RunningCarUpdated);
+        Ok(())
+    }
+
+    fn with_status(&mut self, metadata: &str, status: &str) -> Result<String> {
+        let mut metadata = serde_json::from_str::<serde_json::Value>(metadata)?;
+        metadata
+            .as_object_mut()
+            .ok_or(anyhow!("Metadata is not an object"))?
+            .insert("status".to_string(), status.into());
+
+        let metadata = metadata.to_string();
+        self.validate_metadata(&metadata)?;
+        Ok(metadata)
+    }
+
     fn validate_metadata(&mut self, metadata: &str) -> Result<()> {
         self.meta_schema
             .validate(&serde_json::from_str::<serde_json::Value>(metadata)?)
             .map_err(|_| anyhow!("Metadata validation failed!"))
     }
 
-    fn promote_change(&self) {
+    fn promote_change(&mut self, changes: Vec<Change<RunningCar>>) {
         trace!("Promoting change");
-        if let Err(error) = self.on_change.send(self.running_car.clone()) {
-            error!("Failed to promote change. ({:?})", error);
-        }
+        self.change_log.push(changes);
     }
 }
 
 pub mod server {
     use std::pin::Pin;
-    use std::sync::Arc;
 
     use crate::proto::running_observer as proto;
     use crate::proto::running_observer::{running_observer_server, ReadAllReply};
     use async_trait::async_trait;
     use log::trace;
-    use tokio::sync::Mutex;
     use tokio_stream::Stream;
     use tonic::{Request, Response, Status};
 
-    use super::RunningObserver;
+    use super::{RunningCar, RunningObserver};
+    use crate::actor::CoreHandle;
+    use crate::change_log::{
+        server::{delta_stream, DeltaStream},
+        Change, Message,
+    };
+    use crate::course::CourseState;
+    use crate::event_bus::{changed, Event};
+    use crate::source::Source;
+    use crate::units;
+
+    impl From<&RunningCar> for proto::Item {
+        fn from(running_car: &RunningCar) -> Self {
+            proto::Item {
+                id: running_car.car_id.clone(),
+                start_at: units::to_millis(running_car.start_at),
+                started_at: Some(units::timestamp_to_proto(running_car.start_at)),
+                meta: running_car.meta.clone(),
+                timed_out: running_car.timed_out,
+                start_source: Some((&running_car.start_source).into()),
+            }
+        }
+    }
+
+    impl From<&Change<RunningCar>> for proto::Change {
+        fn from(change: &Change<RunningCar>) -> Self {
+            use proto::change::Change as Kind;
+
+            proto::Change {
+                change: Some(match change {
+                    Change::Inserted { position, item } => Kind::Inserted(proto::Inserted {
+                        position: *position as u32,
+                        item: Some(item.into()),
+                    }),
+                    Change::Updated(item) => Kind::Updated(item.into()),
+                    Change::Removed(id) => Kind::Removed(id.clone()),
+                }),
+            }
+        }
+    }
+
+    impl From<&RunningObserver> for proto::ReadAllReply {
+        fn from(running_observer: &RunningObserver) -> Self {
+            proto::ReadAllReply {
+                item: running_observer
+                    .running_car
+                    .iter()
+                    .map(Into::into)
+                    .collect(),
+                course_state: proto::CourseState::from(running_observer.course_state) as i32,
+            }
+        }
+    }
+
+    fn to_delta_reply(epoch: &str, message: &Message<RunningCar>) -> proto::DeltaReply {
+        match message {
+            Message::Reset { revision, items } => proto::DeltaReply {
+                revision: *revision,
+                reset: true,
+                items: items.iter().map(Into::into).collect(),
+                changes: vec![],
+                epoch: epoch.to_string(),
+            },
+            Message::Delta(delta) => proto::DeltaReply {
+                revision: delta.revision,
+                reset: false,
+                items: vec![],
+                changes: delta.changes.iter().map(Into::into).collect(),
+                epoch: epoch.to_string(),
+            },
+        }
+    }
 
     #[async_trait]
-    impl running_observer_server::RunningObserver for Arc<Mutex<RunningObserver>> {
+    impl running_observer_server::RunningObserver for CoreHandle {
         type SubscribeChangeStream =
             Pin<Box<dyn Stream<Item = Result<proto::ReadAllReply, Status>> + Send>>;
 
+        type SubscribeDeltaStream = DeltaStream<proto::DeltaReply>;
+
         async fn start(
             &self,
             request: Request<proto::StartCommandRequest>,
         ) -> Result<Response<proto::CommandReply>, Status> {
+            let proto::StartCommandRequest {
+                timestamp,
+                pending_car_id,
+                time,
+                source,
+            } = request.into_inner();
+            let timestamp = units::timestamp_from_proto(timestamp, time);
+            let source = Source::try_from(source)?;
+
             match self
-                .lock()
-                .await
-                .start(request.get_ref().timestamp.clone())
-                .await
+                .run(move |core| core.start(timestamp, &pending_car_id, source))
+                .await?
             {
                 Ok(_) => Ok(Response::new(proto::CommandReply {})),
                 Err(error) => Err(Status::failed_precondition(error.to_string())),
@@ -233,9 +614,63 @@ pub mod server {
             &self,
             request: Request<proto::StopCommandRequest>,
         ) -> Result<Response<proto::CommandReply>, Status> {
-            let proto::StopCommandRequest { timestamp, id } = request.get_ref();
+            let proto::StopCommandRequest {
+                timestamp,
+                id,
+                time,
+                source,
+            } = request.into_inner();
+            let timestamp = units::timestamp_from_proto(timestamp, time);
+            let source = Source::try_from(source)?;
+
+            match self
+                .run(move |core| core.stop(timestamp, &id, source))
+                .await?
+            {
+                Ok(_) => Ok(Response::new(proto::CommandReply {})),
+                Err(error) => Err(Status::failed_precondition(error.to_string())),
+            }
+        }
+
+        async fn cancel(
+            &self,
+            request: Request<proto::CancelCommandRequest>,
+        ) -> Result<Response<proto::CommandReply>, Status> {
+            let proto::CancelCommandRequest {
+                timestamp,
+                id,
+                requeue,
+                time,
+            } = request.into_inner();
+            let timestamp = units::timestamp_from_proto(timestamp, time);
+
+            match self
+                .run(move |core| core.cancel(timestamp, &id, requeue))
+                .await?
+            {
+                Ok(_) => Ok(Response::new(proto::CommandReply {})),
+                Err(error) => Err(Status::failed_precondition(error.to_string())),
+            }
+        }
+
+        async fn set_course_state(
+            &self,
+            request: Request<proto::SetCourseStateCommandRequest>,
+        ) -> Result<Response<proto::CommandReply>, Status> {
+            let proto::SetCourseStateCommandRequest {
+                timestamp,
+                state,
+                requeue,
+                time,
+            } = request.into_inner();
+            let timestamp = units::timestamp_from_proto(timestamp, time);
 
-            match self.lock().await.stop(timestamp.clone(), id).await {
+            let state = CourseState::try_from(state)?;
+
+            match self
+                .run(move |core| core.set_course_state(timestamp, state, requeue))
+                .await?
+            {
                 Ok(_) => Ok(Response::new(proto::CommandReply {})),
                 Err(error) => Err(Status::failed_precondition(error.to_string())),
             }
@@ -245,13 +680,17 @@ pub mod server {
             &self,
             request: Request<proto::FlipRunningStateCommandRequest>,
         ) -> Result<Response<proto::CommandReply>, Status> {
-            let proto::FlipRunningStateCommandRequest { timestamp } = request.get_ref();
+            let proto::FlipRunningStateCommandRequest {
+                timestamp,
+                time,
+                source,
+            } = request.into_inner();
+            let timestamp = units::timestamp_from_proto(timestamp, time);
+            let source = Source::try_from(source)?;
 
             match self
-                .lock()
-                .await
-                .flip_start_or_stop(timestamp.clone())
-                .await
+                .run(move |core| core.flip_start_or_stop(timestamp, source))
+                .await?
             {
                 Ok(_) => Ok(Response::new(proto::CommandReply {})),
                 Err(error) => Err(Status::failed_precondition(error.to_string())),
@@ -266,13 +705,16 @@ pub mod server {
                 timestamp,
                 id,
                 metadata,
-            } = request.get_ref();
+                time,
+            } = request.into_inner();
+            let timestamp = units::timestamp_from_proto(timestamp, time);
 
             match self
-                .lock()
-                .await
-                .update_metadata(timestamp.clone(), id, metadata.clone())
-                .await
+                .run(move |core| {
+                    core.running_observer
+                        .update_metadata(timestamp, &id, metadata)
+                })
+                .await?
             {
                 Ok(_) => Ok(Response::new(proto::CommandReply {})),
                 Err(error) => Err(Status::failed_precondition(error.to_string())),
@@ -281,21 +723,12 @@ pub mod server {
 
         async fn read_all(
             &self,
-            request: Request<proto::ReadAllRequest>,
+            _request: Request<proto::ReadAllRequest>,
         ) -> Result<Response<proto::ReadAllReply>, Status> {
-            Ok(tonic::Response::new(proto::ReadAllReply {
-                item: self
-                    .lock()
-                    .await
-                    .running_car
-                    .iter()
-                    .map(|item| proto::Item {
-                        id: item.car_id.clone(),
-                        start_at: item.start_at,
-                        meta: item.meta.clone(),
-                    })
-                    .collect(),
-            }))
+            Ok(tonic::Response::new(
+                self.run(|core| ReadAllReply::from(&core.running_observer))
+                    .await?,
+            ))
         }
 
         async fn subscribe_change(
@@ -303,30 +736,32 @@ pub mod server {
             _request: Request<proto::SubscribeChangeRequest>,
         ) -> Result<tonic::Response<Self::SubscribeChangeStream>, Status> {
             let (tx, rx) = tokio::sync::mpsc::channel(1);
-            let mut watcher = self.lock().await.watcher.clone();
+            let mut events = self.event_bus().subscribe();
+            let core = self.clone();
             tokio::spawn(async move {
-                while watcher.changed().await.is_ok() {
-                    trace!("change received!");
-                    let records = watcher.borrow().clone();
-
-                    match tx
-                        .send(Result::<_, Status>::Ok(ReadAllReply {
-                            item: records
-                                .iter()
-                                .map(|item| proto::Item {
-                                    id: item.car_id.clone(),
-                                    start_at: item.start_at,
-                                    meta: item.meta.clone(),
-                                })
-                                .collect(),
-                        }))
+                loop {
+                    let reply = match core
+                        .run(|core| ReadAllReply::from(&core.running_observer))
                         .await
                     {
+                        Ok(reply) => reply,
+                        Err(stopped) => {
+                            let _ = tx.send(Err(stopped.into())).await;
+                            break;
+                        }
+                    };
+
+                    match tx.send(Result::<_, Status>::Ok(reply)).await {
                         Ok(_) => {}
                         Err(_item) => {
                             break;
                         }
                     }
+
+                    if !changed(&mut events, Event::is_running_observer_event).await {
+                        break;
+                    }
+                    trace!("change received!");
                 }
             });
 
@@ -336,23 +771,46 @@ pub mod server {
                 Box::pin(out_stream) as Self::SubscribeChangeStream
             ))
         }
+
+        async fn subscribe_delta(
+            &self,
+            request: Request<proto::SubscribeDeltaRequest>,
+        ) -> Result<tonic::Response<Self::SubscribeDeltaStream>, Status> {
+            let proto::SubscribeDeltaRequest {
+                since_revision,
+                epoch,
+            } = request.into_inner();
+
+            let resumption = self
+                .run(move |core| {
+                    let observer = &core.running_observer;
+                    observer
+                        .change_log
+                        .resume(&epoch, since_revision, &observer.running_car)
+                })
+                .await?;
+
+            Ok(tonic::Response::new(delta_stream(
+                resumption,
+                to_delta_reply,
+            )))
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::sync::Arc;
-
-    use async_trait::async_trait;
-    use tokio::sync::Mutex;
-
     use crate::config;
-    use crate::config::RecordMetadata;
+    use crate::config::{CourseMode, RecordMetadata, RunTimeout, TimeoutAction};
+    use crate::course::CourseState;
+    use crate::event_bus::{Event, EventBus};
     use crate::prelude::*;
     use crate::running_observer::*;
 
     struct NextCarQueueMock {
         counter: i64,
+        requeued: Vec<MetaData>,
+        rejects_requeue: bool,
     }
 
     struct EmptyNextCarQueueMock;
@@ -361,169 +819,560 @@ mod tests {
         record_lines: Vec<Record>,
     }
 
-    #[async_trait]
     impl RecordService for RecordServiceMock {
-        async fn record(&mut self, record: Record) {
+        fn record(&mut self, record: Record) {
             self.record_lines.push(record);
         }
     }
 
-    #[async_trait]
     impl NextCarQueue for NextCarQueueMock {
-        async fn consume_next_car(&mut self) -> Option<MetaData> {
+        fn consume_next_car(&mut self) -> Option<MetaData> {
             let next = self.counter;
             self.counter += 1;
             Some(format!("{}", next))
         }
+
+        fn consume_car(&mut self, car_id: &str) -> anyhow::Result<MetaData> {
+            Ok(format!("car {}", car_id))
+        }
+
+        fn requeue(&mut self, metas: Vec<MetaData>) -> anyhow::Result<()> {
+            if self.rejects_requeue {
+                anyhow::bail!("Requeue rejected");
+            }
+            self.requeued.extend(metas);
+            Ok(())
+        }
     }
 
-    #[async_trait]
     impl NextCarQueue for EmptyNextCarQueueMock {
-        async fn consume_next_car(&mut self) -> Option<MetaData> {
+        fn consume_next_car(&mut self) -> Option<MetaData> {
             None
         }
+
+        fn consume_car(&mut self, car_id: &str) -> anyhow::Result<MetaData> {
+            anyhow::bail!("No such car {}", car_id)
+        }
+
+        fn requeue(&mut self, _metas: Vec<MetaData>) -> anyhow::Result<()> {
+            Ok(())
+        }
     }
 
-    fn setup() -> (
-        RunningObserver,
-        Arc<Mutex<NextCarQueueMock>>,
-        Arc<Mutex<RecordServiceMock>>,
-    ) {
-        let _ = env_logger::builder().is_test(true).try_init();
-        let config = Config {
-            record: config::Record {
-                metadata: RecordMetadata {
-                    schema: serde_json::from_str(&r#"{"type": "string"}"#).unwrap(),
-                    default: serde_json::from_str(&r#""default_metadata""#).unwrap(),
-                },
+    fn config(mode: CourseMode, max_cars_on_course: Option<usize>) -> Config {
+        Config {
+            course: config::Course {
+                mode,
+                max_cars_on_course,
+                ..config::Course::default()
             },
-            ..Config::default()
-        };
-        let next_car_queue = Arc::new(Mutex::new(NextCarQueueMock { counter: 0 }));
-        let record_service = Arc::new(Mutex::new(RecordServiceMock {
-            record_lines: Vec::new(),
-        }));
+            ..Config::for_test()
+        }
+    }
 
-        let a = next_car_queue.clone();
-        let b = record_service.clone();
-        (
-            RunningObserver::new(&config, next_car_queue, record_service),
-            a,
-            b,
-        )
+    fn setup() -> (RunningObserver, NextCarQueueMock, RecordServiceMock) {
+        setup_with(config(CourseMode::Multi, None))
     }
 
-    fn setup_empty_queue() -> (
-        RunningObserver,
-        Arc<Mutex<EmptyNextCarQueueMock>>,
-        Arc<Mutex<RecordServiceMock>>,
-    ) {
+    fn setup_with(config: Config) -> (RunningObserver, NextCarQueueMock, RecordServiceMock) {
         let _ = env_logger::builder().is_test(true).try_init();
-
-        let config = Config {
-            record: config::Record {
-                metadata: RecordMetadata {
-                    schema: serde_json::from_str(&r#"{"type": "string"}"#).unwrap(),
-                    default: serde_json::from_str(&r#""default_metadata""#).unwrap(),
-                },
+        (
+            RunningObserver::new(&config, EventBus::new()),
+            NextCarQueueMock {
+                counter: 0,
+                requeued: Vec::new(),
+                rejects_requeue: false,
             },
-            ..Config::default()
-        };
+            RecordServiceMock {
+                record_lines: Vec::new(),
+            },
+        )
+    }
 
-        let next_car_queue = Arc::new(Mutex::new(EmptyNextCarQueueMock));
-        let record_service = Arc::new(Mutex::new(RecordServiceMock {
-            record_lines: Vec::new(),
-        }));
+    fn setup_empty_queue() -> (RunningObserver, EmptyNextCarQueueMock, RecordServiceMock) {
+        let _ = env_logger::builder().is_test(true).try_init();
 
-        let a = next_car_queue.clone();
-        let b = record_service.clone();
         (
-            RunningObserver::new(&config, next_car_queue, record_service),
-            a,
-            b,
+            RunningObserver::new(&config(CourseMode::Multi, None), EventBus::new()),
+            EmptyNextCarQueueMock,
+            RecordServiceMock {
+                record_lines: Vec::new(),
+            },
         )
     }
 
-    #[tokio::test]
-    async fn works_when_stopped_with_car_id_not_specified() {
+    #[test]
+    fn works_when_stopped_with_car_id_not_specified() {
         let mut observer = setup();
 
-        observer.0.start(0).await.unwrap();
-        observer.0.stop(10, &None).await.unwrap();
+        observer
+            .0
+            .start(0, Source::Unspecified, &mut observer.1)
+            .unwrap();
+        observer
+            .0
+            .stop(10, &None, Source::Unspecified, &mut observer.2)
+            .unwrap();
 
-        let record = observer.2.lock().await.record_lines.get(0).unwrap().clone();
+        let record = observer.2.record_lines.first().unwrap().clone();
         assert_eq!(record.meta, "0".to_string());
         assert_eq!(record.duration, 10);
     }
 
-    #[tokio::test]
-    async fn works_when_stopped_with_car_id_specified() {
+    #[test]
+    fn sources_are_kept_until_recorded() {
+        let mut observer = setup();
+
+        observer
+            .0
+            .start(0, Source::Sensor("start".to_string()), &mut observer.1)
+            .unwrap();
+        assert_eq!(
+            observer.0.running_car[0].start_source,
+            Source::Sensor("start".to_string())
+        );
+
+        observer
+            .0
+            .stop(10, &None, Source::HandTimed, &mut observer.2)
+            .unwrap();
+
+        let record = observer.2.record_lines.first().unwrap().clone();
+        assert_eq!(record.start_source, Source::Sensor("start".to_string()));
+        assert_eq!(record.stop_source, Source::HandTimed);
+    }
+
+    #[test]
+    fn works_when_stopped_with_car_id_specified() {
         let mut observer = setup();
 
-        observer.0.start(0).await.unwrap();
+        observer
+            .0
+            .start(0, Source::Unspecified, &mut observer.1)
+            .unwrap();
 
         observer
             .0
-            .stop(10, &Some(observer.0.running_car[0].car_id.clone()))
-            .await
+            .stop(
+                10,
+                &Some(observer.0.running_car[0].car_id.clone()),
+                Source::Unspecified,
+                &mut observer.2,
+            )
             .unwrap();
 
-        let record = observer.2.lock().await.record_lines.get(0).unwrap().clone();
+        let record = observer.2.record_lines.first().unwrap().clone();
         assert_eq!(record.meta, "0");
         assert_eq!(record.duration, 10);
     }
 
-    #[tokio::test]
+    #[test]
     #[should_panic]
-    async fn fails_when_stopped_with_car_id_did_not_started_specified() {
+    fn fails_when_stopped_with_car_id_did_not_started_specified() {
         let mut observer = setup();
 
-        observer.0.start(0).await.unwrap();
         observer
             .0
-            .stop(10, &Some("unused_id".to_string()))
-            .await
+            .start(0, Source::Unspecified, &mut observer.1)
+            .unwrap();
+        observer
+            .0
+            .stop(
+                10,
+                &Some("unused_id".to_string()),
+                Source::Unspecified,
+                &mut observer.2,
+            )
             .unwrap();
     }
 
-    #[tokio::test]
-    async fn works_when_multi_cars_started() {
+    #[test]
+    fn works_when_multi_cars_started() {
         let mut observer = setup();
 
-        observer.0.start(0).await.unwrap();
-        observer.0.start(10).await.unwrap();
-        observer.0.stop(20, &None).await.unwrap();
-        observer.0.stop(40, &None).await.unwrap();
+        observer
+            .0
+            .start(0, Source::Unspecified, &mut observer.1)
+            .unwrap();
+        observer
+            .0
+            .start(10, Source::Unspecified, &mut observer.1)
+            .unwrap();
+        observer
+            .0
+            .stop(20, &None, Source::Unspecified, &mut observer.2)
+            .unwrap();
+        observer
+            .0
+            .stop(40, &None, Source::Unspecified, &mut observer.2)
+            .unwrap();
 
-        let record0 = observer.2.lock().await.record_lines.get(0).unwrap().clone();
-        let record1 = observer.2.lock().await.record_lines.get(1).unwrap().clone();
+        let record0 = observer.2.record_lines.first().unwrap().clone();
+        let record1 = observer.2.record_lines.get(1).unwrap().clone();
         assert_eq!(record0.meta, "0".to_string());
         assert_eq!(record0.duration, 20);
         assert_eq!(record1.meta, "1".to_string());
         assert_eq!(record1.duration, 30);
     }
 
-    #[tokio::test]
-    async fn works_when_flip_start_or_stop_used() {
-        let mut observer = setup();
+    #[test]
+    fn works_when_flip_start_or_stop_used() {
+        let mut observer = setup_with(config(CourseMode::Flip, None));
 
-        observer.0.flip_start_or_stop(0).await.unwrap();
-        observer.0.flip_start_or_stop(10).await.unwrap();
+        observer
+            .0
+            .flip_start_or_stop(0, Source::Unspecified, &mut observer.1, &mut observer.2)
+            .unwrap();
+        observer
+            .0
+            .flip_start_or_stop(10, Source::Unspecified, &mut observer.1, &mut observer.2)
+            .unwrap();
 
-        let record = observer.2.lock().await.record_lines.get(0).unwrap().clone();
+        let record = observer.2.record_lines.first().unwrap().clone();
         assert_eq!(record.meta, "0".to_string());
         assert_eq!(record.duration, 10);
     }
 
-    #[tokio::test]
-    async fn works_when_empty_queue_used() {
+    #[test]
+    fn fails_when_started_on_full_course() {
+        let mut observer = setup_with(config(CourseMode::Multi, Some(2)));
+
+        observer
+            .0
+            .start(0, Source::Unspecified, &mut observer.1)
+            .unwrap();
+        observer
+            .0
+            .start(10, Source::Unspecified, &mut observer.1)
+            .unwrap();
+        observer
+            .0
+            .start(20, Source::Unspecified, &mut observer.1)
+            .unwrap_err();
+        assert_eq!(observer.0.running_car.len(), 2);
+
+        observer
+            .0
+            .stop(30, &None, Source::Unspecified, &mut observer.2)
+            .unwrap();
+        observer
+            .0
+            .start(40, Source::Unspecified, &mut observer.1)
+            .unwrap();
+
+        observer
+            .0
+            .flip_start_or_stop(50, Source::Unspecified, &mut observer.1, &mut observer.2)
+            .unwrap_err();
+    }
+
+    #[test]
+    fn flip_mode_allows_only_one_car() {
+        let mut observer = setup_with(config(CourseMode::Flip, None));
+
+        observer
+            .0
+            .start(0, Source::Unspecified, &mut observer.1)
+            .unwrap();
+        observer
+            .0
+            .start(10, Source::Unspecified, &mut observer.1)
+            .unwrap_err();
+        assert_eq!(observer.1.counter, 1);
+    }
+
+    #[test]
+    fn works_when_empty_queue_used() {
         let mut observer = setup_empty_queue();
 
-        observer.0.start(0).await.unwrap();
-        observer.0.stop(10, &None).await.unwrap();
+        observer
+            .0
+            .start(0, Source::Unspecified, &mut observer.1)
+            .unwrap();
+        observer
+            .0
+            .stop(10, &None, Source::Unspecified, &mut observer.2)
+            .unwrap();
 
-        let record = observer.2.lock().await.record_lines.get(0).unwrap().clone();
+        let record = observer.2.record_lines.first().unwrap().clone();
         assert_eq!(record.meta, r#""default_metadata""#.to_string());
         assert_eq!(record.duration, 10);
     }
+
+    #[test]
+    fn works_when_started_with_pending_car_id() {
+        let mut observer = setup();
+
+        observer
+            .0
+            .start_car(0, "3", Source::Unspecified, &mut observer.1)
+            .unwrap();
+        observer
+            .0
+            .start(10, Source::Unspecified, &mut observer.1)
+            .unwrap();
+
+        assert_eq!(observer.0.running_car[0].meta, "car 3");
+        assert_eq!(observer.0.running_car[1].meta, "0");
+    }
+
+    #[test]
+    fn fails_when_started_with_unknown_pending_car_id() {
+        let mut observer = setup_empty_queue();
+
+        observer
+            .0
+            .start_car(0, "3", Source::Unspecified, &mut observer.1)
+            .unwrap_err();
+
+        assert!(observer.0.running_car.is_empty());
+    }
+
+    #[test]
+    fn works_when_cancelled() {
+        let mut observer = setup();
+
+        observer
+            .0
+            .start(0, Source::Unspecified, &mut observer.1)
+            .unwrap();
+        observer
+            .0
+            .start(10, Source::Unspecified, &mut observer.1)
+            .unwrap();
+        observer
+            .0
+            .cancel(20, &None, false, &mut observer.1)
+            .unwrap();
+        observer.0.cancel(30, &None, true, &mut observer.1).unwrap();
+
+        assert!(observer.0.running_car.is_empty());
+        assert!(observer.2.record_lines.is_empty());
+        assert_eq!(observer.1.requeued, vec!["1".to_string()]);
+    }
+
+    #[test]
+    fn red_flag_requeues_running_cars_and_refuses_start() {
+        let mut observer = setup();
+
+        observer
+            .0
+            .start(0, Source::Unspecified, &mut observer.1)
+            .unwrap();
+        observer
+            .0
+            .start(10, Source::Unspecified, &mut observer.1)
+            .unwrap();
+
+        observer
+            .0
+            .set_course_state(20, CourseState::RedFlag, true, &mut observer.1)
+            .unwrap();
+
+        assert!(observer.0.running_car.is_empty());
+        assert!(observer.2.record_lines.is_empty());
+        assert_eq!(observer.1.requeued, vec!["0".to_string(), "1".to_string()]);
+
+        observer
+            .0
+            .start(30, Source::Unspecified, &mut observer.1)
+            .unwrap_err();
+
+        observer
+            .0
+            .set_course_state(40, CourseState::Green, false, &mut observer.1)
+            .unwrap();
+        observer
+            .0
+            .start(50, Source::Unspecified, &mut observer.1)
+            .unwrap();
+
+        observer
+            .0
+            .set_course_state(60, CourseState::Closed, false, &mut observer.1)
+            .unwrap_err();
+    }
+
+    #[test]
+    fn red_flag_changes_nothing_if_requeue_is_rejected() {
+        let mut observer = setup();
+
+        observer
+            .0
+            .start(0, Source::Unspecified, &mut observer.1)
+            .unwrap();
+        observer
+            .0
+            .start(10, Source::Unspecified, &mut observer.1)
+            .unwrap();
+
+        observer.1.rejects_requeue = true;
+        observer
+            .0
+            .set_course_state(20, CourseState::RedFlag, true, &mut observer.1)
+            .unwrap_err();
+
+        assert_eq!(observer.0.course_state(), CourseState::Green);
+        assert_eq!(observer.0.running_car.len(), 2);
+        observer
+            .0
+            .start(30, Source::Unspecified, &mut observer.1)
+            .unwrap();
+    }
+
+    fn config_with_timeout(action: TimeoutAction) -> Config {
+        let mut config = config(CourseMode::Multi, None);
+        config.record.metadata = RecordMetadata {
+            schema: serde_json::from_str(
+                r#"{"type": "object", "properties": {"status": {"enum": ["DNF"]}}}"#,
+            )
+            .unwrap(),
+            default: serde_json::from_str(r#"{}"#).unwrap(),
+        };
+        config.course.timeout = Some(RunTimeout {
+            max_run_time_ms: 100,
+            action,
+        });
+        config
+    }
+
+    #[test]
+    fn stops_car_as_dnf_on_timeout() {
+        let mut observer = setup_with(config_with_timeout(TimeoutAction::Dnf));
+        let mut next_car_queue = EmptyNextCarQueueMock;
+
+        observer
+            .0
+            .start(0, Source::Unspecified, &mut next_car_queue)
+            .unwrap();
+        observer
+            .0
+            .start(50_000, Source::Unspecified, &mut next_car_queue)
+            .unwrap();
+        assert_eq!(observer.0.next_timeout(), Some(100_000));
+
+        observer.0.expire(99_999, &mut observer.2).unwrap();
+        assert_eq!(observer.0.running_car.len(), 2);
+
+        observer.0.expire(100_000, &mut observer.2).unwrap();
+        assert_eq!(observer.0.running_car.len(), 1);
+        assert_eq!(observer.0.next_timeout(), Some(150_000));
+
+        let deltas = observer.0.change_log.since(2).unwrap();
+        assert_eq!(deltas.len(), 1);
+        assert!(matches!(&deltas[0].changes[..], [Change::Removed(_)]));
+
+        let record = observer.2.record_lines.first().unwrap().clone();
+        assert_eq!(record.meta, r#"{"status":"DNF"}"#);
+        assert_eq!(record.duration, 100_000);
+        assert_eq!(record.stop_source, Source::Timeout);
+    }
+
+    #[test]
+    fn flagged_car_is_skipped_on_stop() {
+        let mut observer = setup_with(config_with_timeout(TimeoutAction::Flag));
+        let mut next_car_queue = EmptyNextCarQueueMock;
+
+        observer
+            .0
+            .start(0, Source::Unspecified, &mut next_car_queue)
+            .unwrap();
+        observer
+            .0
+            .start(50_000, Source::Unspecified, &mut next_car_queue)
+            .unwrap();
+        observer.0.expire(120_000, &mut observer.2).unwrap();
+
+        assert!(observer.0.running_car[0].timed_out);
+        assert!(!observer.0.running_car[1].timed_out);
+        assert_eq!(observer.0.next_timeout(), Some(150_000));
+
+        observer
+            .0
+            .stop(130_000, &None, Source::Unspecified, &mut observer.2)
+            .unwrap();
+
+        assert_eq!(observer.2.record_lines[0].duration, 80_000);
+        assert!(observer.0.running_car[0].timed_out);
+
+        observer
+            .0
+            .stop(140_000, &None, Source::Unspecified, &mut observer.2)
+            .unwrap_err();
+        assert_eq!(observer.2.record_lines.len(), 1);
+    }
+
+    #[test]
+    fn flagged_car_leaves_room_for_next_flip() {
+        let mut config = config_with_timeout(TimeoutAction::Flag);
+        config.course.mode = CourseMode::Flip;
+        let mut observer = setup_with(config);
+
+        observer
+            .0
+            .flip_start_or_stop(0, Source::Unspecified, &mut observer.1, &mut observer.2)
+            .unwrap();
+        observer.0.expire(100_000, &mut observer.2).unwrap();
+
+        // NOTE: The next trigger is the next car starting, not the broken down one finishing.
+        observer
+            .0
+            .flip_start_or_stop(
+                120_000,
+                Source::Unspecified,
+                &mut observer.1,
+                &mut observer.2,
+            )
+            .unwrap();
+        assert!(observer.2.record_lines.is_empty());
+        assert_eq!(observer.0.running_car.len(), 2);
+
+        observer
+            .0
+            .flip_start_or_stop(
+                150_000,
+                Source::Unspecified,
+                &mut observer.1,
+                &mut observer.2,
+            )
+            .unwrap();
+        assert_eq!(observer.2.record_lines.len(), 1);
+        assert_eq!(observer.2.record_lines[0].duration, 30_000);
+        assert!(observer.0.running_car[0].timed_out);
+    }
+
+    #[test]
+    #[should_panic(expected = "Timeout action dnf")]
+    fn dnf_needs_object_metadata() {
+        let mut config = config(CourseMode::Multi, None);
+        config.course.timeout = Some(RunTimeout {
+            max_run_time_ms: 100,
+            action: TimeoutAction::Dnf,
+        });
+
+        setup_with(config);
+    }
+
+    #[test]
+    fn publishes_start_and_stop_events() {
+        let (observer, mut next_car_queue, mut record_service) = setup();
+        let event_bus = EventBus::new();
+        let mut events = event_bus.subscribe();
+
+        let mut observer = RunningObserver {
+            event_bus,
+            ..observer
+        };
+
+        observer
+            .start(0, Source::Unspecified, &mut next_car_queue)
+            .unwrap();
+        observer
+            .stop(10, &None, Source::Unspecified, &mut record_service)
+            .unwrap();
+
+        assert_eq!(events.try_recv().unwrap(), Event::CarStarted);
+        assert_eq!(events.try_recv().unwrap(), Event::CarStopped);
+        events.try_recv().unwrap_err();
+    }
 }
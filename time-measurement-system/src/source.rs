@@ -0,0 +1,94 @@
+/// Who issued a start or stop, so that results show which times were not taken by a sensor.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum Source {
+    /// Sent by a client which does not tell.
+    #[default]
+    Unspecified,
+    /// An input of sensor-io, with its id.
+    Sensor(String),
+    /// A marshal pressing a button as the car passes.
+    Manual,
+    /// A marshal's stopwatch as the backup of a failed sensor.
+    HandTimed,
+    /// The server stopping a car which exceeded the max run time.
+    Timeout,
+}
+
+impl Source {
+    pub fn is_hand_timed(&self) -> bool {
+        matches!(self, Source::Manual | Source::HandTimed)
+    }
+}
+
+pub mod server {
+    use tonic::Status;
+
+    use super::Source;
+    use crate::proto::running_observer::{self, source::Kind};
+
+    impl From<&Source> for running_observer::Source {
+        fn from(source: &Source) -> Self {
+            let (kind, sensor_id) = match source {
+                Source::Unspecified => (Kind::Unspecified, String::new()),
+                Source::Sensor(id) => (Kind::Sensor, id.clone()),
+                Source::Manual => (Kind::Manual, String::new()),
+                Source::HandTimed => (Kind::HandTimed, String::new()),
+                Source::Timeout => (Kind::Timeout, String::new()),
+            };
+
+            running_observer::Source {
+                kind: kind as i32,
+                sensor_id,
+            }
+        }
+    }
+
+    impl TryFrom<Option<running_observer::Source>> for Source {
+        type Error = Status;
+
+        fn try_from(source: Option<running_observer::Source>) -> Result<Self, Self::Error> {
+            let Some(source) = source else {
+                return Ok(Source::Unspecified);
+            };
+
+            match Kind::from_i32(source.kind) {
+                Some(Kind::Unspecified) => Ok(Source::Unspecified),
+                Some(Kind::Sensor) => Ok(Source::Sensor(source.sensor_id)),
+                Some(Kind::Manual) => Ok(Source::Manual),
+                Some(Kind::HandTimed) => Ok(Source::HandTimed),
+                Some(Kind::Timeout) => Ok(Source::Timeout),
+                None => Err(Status::invalid_argument(format!(
+                    "Unknown source kind {}",
+                    source.kind
+                ))),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Source;
+    use crate::proto::running_observer as proto;
+
+    #[test]
+    fn converts_from_proto() {
+        assert_eq!(
+            Source::try_from(None::<proto::Source>).unwrap(),
+            Source::Unspecified
+        );
+        assert_eq!(
+            Source::try_from(Some(proto::Source {
+                kind: proto::source::Kind::Sensor as i32,
+                sensor_id: "finish".to_string(),
+            }))
+            .unwrap(),
+            Source::Sensor("finish".to_string())
+        );
+        Source::try_from(Some(proto::Source {
+            kind: 42,
+            sensor_id: String::new(),
+        }))
+        .unwrap_err();
+    }
+}
@@ -0,0 +1,60 @@
+use log::{error, trace};
+
+use crate::{
+    actor::CoreHandle,
+    event_bus::{changed, Event},
+    prelude::*,
+};
+
+fn get_unixtime_us() -> TimeStamp {
+    std::time::SystemTime::now()
+        .duration_since(std::time::SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_micros() as TimeStamp
+}
+
+/// Spawns a task which applies the configured timeout action when running cars exceed the max
+/// run time.
+pub fn spawn(core: CoreHandle) {
+    tokio::spawn(async move {
+        let mut events = core.event_bus().subscribe();
+
+        loop {
+            let now = get_unixtime_us();
+
+            let Ok(expired) = core.run(move |core| core.expire(now)).await else {
+                break;
+            };
+            if let Err(error) = expired {
+                error!("Failed to expire running cars. {:?}", error);
+            }
+
+            let Ok(next_timeout) = core.run(|core| core.running_observer.next_timeout()).await
+            else {
+                break;
+            };
+
+            // NOTE: Cars started or stopped meanwhile change the next timeout.
+            match next_timeout {
+                Some(next_timeout) => {
+                    let wait = (next_timeout - get_unixtime_us()).max(0) as u64;
+                    trace!("Next timeout in {}us", wait);
+
+                    tokio::select! {
+                        _ = tokio::time::sleep(std::time::Duration::from_micros(wait)) => {}
+                        running = changed(&mut events, Event::is_running_observer_event) => {
+                            if !running {
+                                break;
+                            }
+                        }
+                    }
+                }
+                None => {
+                    if !changed(&mut events, Event::is_running_observer_event).await {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,135 @@
+use crate::{
+    config::{Display, Rounding},
+    prelude::*,
+};
+
+/// `TimeStamp` and `Duration` are microseconds. Legacy proto fields are milliseconds.
+pub const MICROS_PER_MILLI: i64 = 1_000;
+const MICROS_PER_SECOND: i64 = 1_000_000;
+const NANOS_PER_MICRO: i64 = 1_000;
+
+pub fn from_millis(millis: i64) -> i64 {
+    millis * MICROS_PER_MILLI
+}
+
+pub fn to_millis(micros: i64) -> i64 {
+    micros.div_euclid(MICROS_PER_MILLI)
+}
+
+/// Prefers `time` if set, otherwise takes the legacy milliseconds.
+pub fn timestamp_from_proto(legacy: i64, time: Option<prost_types::Timestamp>) -> TimeStamp {
+    match time {
+        Some(time) => time.seconds * MICROS_PER_SECOND + time.nanos as i64 / NANOS_PER_MICRO,
+        None => from_millis(legacy),
+    }
+}
+
+pub fn timestamp_to_proto(timestamp: TimeStamp) -> prost_types::Timestamp {
+    prost_types::Timestamp {
+        seconds: timestamp.div_euclid(MICROS_PER_SECOND),
+        nanos: (timestamp.rem_euclid(MICROS_PER_SECOND) * NANOS_PER_MICRO) as i32,
+    }
+}
+
+/// Prefers `duration` if set, otherwise takes the legacy milliseconds.
+pub fn duration_from_proto(legacy: i64, duration: Option<prost_types::Duration>) -> Duration {
+    match duration {
+        Some(duration) => {
+            duration.seconds * MICROS_PER_SECOND + duration.nanos as i64 / NANOS_PER_MICRO
+        }
+        None => from_millis(legacy),
+    }
+}
+
+pub fn duration_to_proto(duration: Duration) -> prost_types::Duration {
+    // NOTE: Unlike timestamps, nanos have the same sign as seconds.
+    prost_types::Duration {
+        seconds: duration / MICROS_PER_SECOND,
+        nanos: (duration % MICROS_PER_SECOND * NANOS_PER_MICRO) as i32,
+    }
+}
+
+/// Formats `duration` in seconds with `display.precision` decimal places, e.g. `12.345`.
+pub fn format_duration(duration: Duration, display: &Display) -> String {
+    let precision = display.precision.min(6);
+    let unit = 10_i64.pow(6 - precision);
+
+    let magnitude = duration.abs();
+    let units = match display.rounding {
+        Rounding::Truncate => magnitude / unit,
+        Rounding::HalfUp => (magnitude + unit / 2) / unit,
+        Rounding::Up => (magnitude + unit - 1) / unit,
+    };
+
+    let sign = if duration < 0 && units != 0 { "-" } else { "" };
+    let scale = 10_i64.pow(precision);
+
+    if precision == 0 {
+        format!("{}{}", sign, units)
+    } else {
+        format!(
+            "{}{}.{:0width$}",
+            sign,
+            units / scale,
+            units % scale,
+            width = precision as usize
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_proto_times() {
+        let timestamp = 1_700_000_000_123_456;
+        assert_eq!(
+            timestamp_from_proto(0, Some(timestamp_to_proto(timestamp))),
+            timestamp
+        );
+        assert_eq!(
+            timestamp_from_proto(1_700_000_000_123, None),
+            1_700_000_000_123_000
+        );
+
+        assert_eq!(
+            duration_from_proto(0, Some(duration_to_proto(-1_500_001))),
+            -1_500_001
+        );
+        assert_eq!(to_millis(12_345_678), 12_345);
+    }
+
+    #[test]
+    fn formats_durations() {
+        let display = |precision, rounding| Display {
+            precision,
+            rounding,
+        };
+
+        assert_eq!(
+            format_duration(12_345_678, &display(3, Rounding::Truncate)),
+            "12.345"
+        );
+        assert_eq!(
+            format_duration(12_345_678, &display(3, Rounding::HalfUp)),
+            "12.346"
+        );
+        assert_eq!(
+            format_duration(12_340_001, &display(2, Rounding::Up)),
+            "12.35"
+        );
+        assert_eq!(
+            format_duration(12_999_999, &display(0, Rounding::HalfUp)),
+            "13"
+        );
+        assert_eq!(
+            format_duration(5_000, &display(6, Rounding::Truncate)),
+            "0.005000"
+        );
+        assert_eq!(
+            format_duration(-1_500_000, &display(1, Rounding::Truncate)),
+            "-1.5"
+        );
+    }
+}